@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::chip8::{Instruction, MEMORY_SIZE};
+
+/// Address programs are loaded at, and so the address of the first byte assembled.
+const ORIGIN: usize = 0x200;
+
+/// Maximum depth of nested includes, to catch include cycles.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An error found while assembling, with the file and line number it was found on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// One line of source, after includes have been expanded.
+struct SourceLine {
+    file: String,
+    number: usize,
+    text: String,
+}
+
+/// A statement, positioned at the address it will be assembled to.
+struct Statement<'a> {
+    line: &'a SourceLine,
+    address: usize,
+    kind: StatementKind,
+}
+
+enum StatementKind {
+    Instruction { mnemonic: String, operands: Vec<String> },
+    Data { width: usize, values: Vec<String> },
+    Sprite(Vec<String>),
+    Org(usize),
+}
+
+/// A name defined by a label or an `EQU` directive.
+enum Symbol {
+    Address(usize),
+    Constant { expression: String, file: String, line: usize },
+}
+
+/// Assembles the source file at `path` into a ROM that can be loaded at address 0x200.
+/// `INCLUDE` paths are relative to the file containing them. All errors found are returned, in source order.
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, Vec<AssembleError>> {
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    read_source(path, None, 0, &mut lines, &mut errors);
+    match assemble_lines(&lines) {
+        Ok(rom) if errors.is_empty() => Ok(rom),
+        Ok(_) => Err(errors),
+        Err(more) => { errors.extend(more); Err(errors) },
+    }
+}
+
+/// Assembles source text into a ROM that can be loaded at address 0x200. `name` is used in error messages,
+/// and `INCLUDE` paths are relative to the current directory.
+pub fn assemble(source: &str, name: &str) -> Result<Vec<u8>, Vec<AssembleError>> {
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    expand_source(source, name, Path::new("."), 0, &mut lines, &mut errors);
+    match assemble_lines(&lines) {
+        Ok(rom) if errors.is_empty() => Ok(rom),
+        Ok(_) => Err(errors),
+        Err(more) => { errors.extend(more); Err(errors) },
+    }
+}
+
+/// Reads a source file, expanding its includes into `lines`.
+fn read_source(path: &Path, included_from: Option<(&str, usize)>, depth: usize, lines: &mut Vec<SourceLine>, errors: &mut Vec<AssembleError>) {
+    let name = path.display().to_string();
+    match fs::read_to_string(path) {
+        Ok(source) => {
+            let directory = path.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+            expand_source(&source, &name, &directory, depth, lines, errors);
+        },
+        Err(error) => {
+            let (file, line) = included_from.unwrap_or((&name, 0));
+            errors.push(AssembleError { file: file.to_string(), line, message: format!("unable to read {}: {}", name, error) });
+        },
+    }
+}
+
+/// Splits source text into lines, replacing `INCLUDE "file"` lines with the contents of the file.
+fn expand_source(source: &str, name: &str, directory: &Path, depth: usize, lines: &mut Vec<SourceLine>, errors: &mut Vec<AssembleError>) {
+    for (index, text) in source.lines().enumerate() {
+        let code = strip_comment(text).trim();
+        let is_include = code.split_whitespace().next().map(|word| word.eq_ignore_ascii_case("include")).unwrap_or(false);
+        if !is_include {
+            lines.push(SourceLine { file: name.to_string(), number: index + 1, text: text.to_string() });
+            continue;
+        }
+
+        let argument = code[7..].trim();
+        let error = |message: String| AssembleError { file: name.to_string(), line: index + 1, message };
+        if depth >= MAX_INCLUDE_DEPTH {
+            errors.push(error("includes are nested too deeply (is a file including itself?)".to_string()));
+        } else if argument.len() >= 2 && argument.starts_with('"') && argument.ends_with('"') {
+            read_source(&directory.join(&argument[1..argument.len() - 1]), Some((name, index + 1)), depth + 1, lines, errors);
+        } else {
+            errors.push(error("INCLUDE expects a quoted file name".to_string()));
+        }
+    }
+}
+
+/// Removes a `;` comment from a line, ignoring any inside quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in text.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &text[..index],
+            _ => { },
+        }
+    }
+    text
+}
+
+/// Assembles expanded source lines in two passes: the first finds the address of every label, the second encodes.
+fn assemble_lines(lines: &[SourceLine]) -> Result<Vec<u8>, Vec<AssembleError>> {
+    let mut errors = Vec::new();
+    let mut symbols: HashMap<String, Symbol> = HashMap::new();
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut address = ORIGIN;
+
+    // First pass: parse statements, assign addresses and collect symbols
+    for line in lines {
+        let error = |message: String| AssembleError { file: line.file.clone(), line: line.number, message };
+        let mut code = strip_comment(&line.text).trim();
+
+        // Labels: `name:` at the start of a line
+        if let Some(colon) = code.find(':') {
+            let name = code[..colon].trim();
+            if is_identifier(name) && !code[..colon].contains('"') {
+                if symbols.insert(name.to_string(), Symbol::Address(address)).is_some() {
+                    errors.push(error(format!("'{}' is already defined", name)));
+                }
+                code = code[colon + 1..].trim();
+            }
+        }
+        if code.is_empty() { continue; }
+
+        let (mnemonic, rest) = match code.find(char::is_whitespace) {
+            Some(space) => (&code[..space], code[space..].trim()),
+            None => (code, ""),
+        };
+        let operands = split_operands(rest);
+
+        // Constants: `name EQU value`
+        if let Some((name, value)) = rest.split_once(char::is_whitespace).filter(|(keyword, _)| keyword.eq_ignore_ascii_case("equ")).map(|(_, value)| (mnemonic, value.trim())) {
+            if !is_identifier(name) {
+                errors.push(error(format!("'{}' is not a valid constant name", name)));
+            } else if symbols.insert(name.to_string(), Symbol::Constant { expression: value.to_string(), file: line.file.clone(), line: line.number }).is_some() {
+                errors.push(error(format!("'{}' is already defined", name)));
+            }
+            continue;
+        }
+
+        let kind = match mnemonic.to_uppercase().as_str() {
+            "DB" => StatementKind::Data { width: 1, values: operands },
+            "DW" => StatementKind::Data { width: 2, values: operands },
+            "SPRITE" => StatementKind::Sprite(operands),
+            "ORG" => {
+                let target = match operands.as_slice() {
+                    [target] => evaluate(target, &symbols, 0),
+                    _ => Err("ORG expects one address".to_string()),
+                };
+                match target {
+                    Ok(target) if target as usize >= address => StatementKind::Org(target as usize),
+                    Ok(target) => { errors.push(error(format!("ORG 0x{:X} is before the current address 0x{:X}", target, address))); continue; },
+                    Err(message) => { errors.push(error(message)); continue; },
+                }
+            },
+            _ => StatementKind::Instruction { mnemonic: mnemonic.to_uppercase(), operands },
+        };
+
+        let size = match &kind {
+            StatementKind::Instruction { mnemonic, operands } => {
+                let long = mnemonic == "LD" && operands.get(1).map(|operand| operand.to_uppercase().starts_with("LONG ")).unwrap_or(false);
+                if long { 4 } else { 2 }
+            },
+            StatementKind::Data { width, values } => width * values.len(),
+            StatementKind::Sprite(rows) => rows.iter().map(|row| row.trim_matches('"').len().div_ceil(8)).sum(),
+            StatementKind::Org(target) => target - address,
+        };
+        statements.push(Statement { line, address, kind });
+        address += size;
+    }
+
+    if address > MEMORY_SIZE {
+        let last = lines.last().map(|line| (line.file.clone(), line.number)).unwrap_or_default();
+        errors.push(AssembleError { file: last.0, line: last.1, message: format!("program is {} bytes, but at most {} bytes can be loaded", address - ORIGIN, MEMORY_SIZE - ORIGIN) });
+        return Err(errors);
+    }
+
+    // Second pass: encode statements, now every label is known
+    let mut rom = vec![0; address - ORIGIN];
+    for statement in &statements {
+        let line = statement.line;
+        let bytes = match encode(&statement.kind, &symbols) {
+            Ok(bytes) => bytes,
+            Err(message) => { errors.push(AssembleError { file: line.file.clone(), line: line.number, message }); continue; },
+        };
+        let offset = statement.address - ORIGIN;
+        rom[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+
+    if errors.is_empty() { Ok(rom) } else { Err(errors) }
+}
+
+/// Splits comma-separated operands, keeping commas inside quotes.
+fn split_operands(text: &str) -> Vec<String> {
+    if text.is_empty() { return Vec::new(); }
+    let mut operands = vec![String::new()];
+    let mut quoted = false;
+    for character in text.chars() {
+        match character {
+            '"' => { quoted = !quoted; operands.last_mut().unwrap().push(character); },
+            ',' if !quoted => operands.push(String::new()),
+            _ => operands.last_mut().unwrap().push(character),
+        }
+    }
+    operands.iter().map(|operand| operand.trim().to_string()).collect()
+}
+
+/// Returns true if `name` can be used as a label or constant: letters, digits and underscores, not starting with a
+/// digit, and not a register name.
+fn is_identifier(name: &str) -> bool {
+    let mut characters = name.chars();
+    let valid = matches!(characters.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_');
+    valid && register(name).is_none()
+}
+
+/// Parses a register name (V0 through VF).
+fn register(operand: &str) -> Option<u8> {
+    let upper = operand.to_uppercase();
+    let digit = upper.strip_prefix('V')?;
+    if digit.len() != 1 { return None; }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+/// Evaluates an expression: numbers, labels and constants, added and subtracted.
+/// Numbers are decimal, hexadecimal with a `0x` prefix, or binary with a `0b` prefix.
+fn evaluate(expression: &str, symbols: &HashMap<String, Symbol>, depth: usize) -> Result<i64, String> {
+    if depth > MAX_INCLUDE_DEPTH { return Err(format!("constant '{}' is defined in terms of itself", expression)); }
+
+    let mut total: i64 = 0;
+    let mut sign: i64 = 1;
+    let mut term = String::new();
+    let mut terms: Vec<(i64, String)> = Vec::new();
+    for character in expression.chars() {
+        match character {
+            '+' | '-' => {
+                if term.trim().is_empty() && !terms.is_empty() { return Err(format!("invalid expression '{}'", expression)); }
+                if !term.trim().is_empty() { terms.push((sign, term.trim().to_string())); }
+                term.clear();
+                sign = if character == '-' { -1 } else { 1 };
+            },
+            _ => term.push(character),
+        }
+    }
+    if term.trim().is_empty() { return Err(format!("invalid expression '{}'", expression)); }
+    terms.push((sign, term.trim().to_string()));
+
+    for (sign, term) in terms {
+        let lower = term.to_lowercase();
+        let value = if let Some(hex) = lower.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).map_err(|_| format!("invalid number '{}'", term))?
+        } else if let Some(binary) = lower.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).map_err(|_| format!("invalid number '{}'", term))?
+        } else if term.starts_with(|character: char| character.is_ascii_digit()) {
+            term.parse().map_err(|_| format!("invalid number '{}'", term))?
+        } else {
+            match symbols.get(&term) {
+                Some(Symbol::Address(address)) => *address as i64,
+                Some(Symbol::Constant { expression, file, line }) => evaluate(expression, symbols, depth + 1)
+                    .map_err(|message| format!("{} (in constant '{}' defined at {}:{})", message, term, file, line))?,
+                None => return Err(format!("undefined name '{}'", term)),
+            }
+        };
+        total += sign * value;
+    }
+    Ok(total)
+}
+
+/// Evaluates an expression, checking it lies in `0..=max`.
+fn value(expression: &str, symbols: &HashMap<String, Symbol>, max: i64, what: &str) -> Result<u16, String> {
+    let value = evaluate(expression, symbols, 0)?;
+    // Negative bytes are allowed, and stored in two's complement
+    if max == 0xFF && (-128..0).contains(&value) { return Ok((value + 256) as u16); }
+    if !(0..=max).contains(&value) {
+        return Err(format!("{} '{}' is out of range (0x{:X}, maximum 0x{:X})", what, expression, value, max));
+    }
+    Ok(value as u16)
+}
+
+/// Encodes a statement as bytes.
+fn encode(kind: &StatementKind, symbols: &HashMap<String, Symbol>) -> Result<Vec<u8>, String> {
+    match kind {
+        StatementKind::Instruction { mnemonic, operands } => Ok(parse_instruction(mnemonic, operands, symbols)?.encode()),
+        StatementKind::Data { width: 1, values } => values.iter().map(|expression| value(expression, symbols, 0xFF, "byte").map(|byte| byte as u8)).collect(),
+        StatementKind::Data { values, .. } => {
+            let mut bytes = Vec::new();
+            for expression in values {
+                let word = value(expression, symbols, 0xFFFF, "word")?;
+                bytes.extend_from_slice(&word.to_be_bytes());
+            }
+            Ok(bytes)
+        },
+        StatementKind::Sprite(rows) => {
+            let mut bytes = Vec::new();
+            for row in rows {
+                if row.len() < 2 || !row.starts_with('"') || !row.ends_with('"') {
+                    return Err(format!("sprite row {} must be a quoted string of '#' and '.'", row));
+                }
+                let pixels = &row[1..row.len() - 1];
+                let mut padded: Vec<bool> = Vec::new();
+                for pixel in pixels.chars() {
+                    match pixel {
+                        '#' | '1' | 'X' | 'x' => padded.push(true),
+                        '.' | '0' | ' ' | '_' => padded.push(false),
+                        other => return Err(format!("invalid sprite pixel '{}' (use '#' for lit and '.' for unlit)", other)),
+                    }
+                }
+                padded.resize(pixels.len().div_ceil(8) * 8, false);
+                for chunk in padded.chunks(8) {
+                    bytes.push(chunk.iter().fold(0u8, |byte, lit| byte << 1 | *lit as u8));
+                }
+            }
+            Ok(bytes)
+        },
+        StatementKind::Org(_) => Ok(Vec::new()),
+    }
+}
+
+/// Parses an instruction from its mnemonic and operands.
+fn parse_instruction(mnemonic: &str, operands: &[String], symbols: &HashMap<String, Symbol>) -> Result<Instruction, String> {
+    let upper: Vec<String> = operands.iter().map(|operand| operand.to_uppercase()).collect();
+    let words: Vec<&str> = upper.iter().map(String::as_str).collect();
+    let registers: Vec<Option<u8>> = operands.iter().map(|operand| register(operand)).collect();
+    let byte = |index: usize| value(&operands[index], symbols, 0xFF, "byte").map(|byte| byte as u8);
+    let address = |index: usize| value(&operands[index], symbols, 0xFFF, "address");
+    let nibble = |index: usize| value(&operands[index], symbols, 0xF, "value").map(|nibble| nibble as u8);
+
+    let instruction = match (mnemonic, words.as_slice(), registers.as_slice()) {
+        ("CLS", [], _) => Instruction::Clear,
+        ("RET", [], _) => Instruction::Return,
+        ("SCD", [_], _) => Instruction::ScrollDown(nibble(0)?),
+        ("SCU", [_], _) => Instruction::ScrollUp(nibble(0)?),
+        ("SCR", [], _) => Instruction::ScrollRight,
+        ("SCL", [], _) => Instruction::ScrollLeft,
+        ("EXIT", [], _) => Instruction::Exit,
+        ("LOW", [], _) => Instruction::Lores,
+        ("HIGH", [], _) => Instruction::Hires,
+        ("JP", [_], [None]) => Instruction::Jump(address(0)?),
+        ("JP", ["V0", _], [_, None]) => Instruction::JumpOffset(address(1)?),
+        ("CALL", [_], [None]) => Instruction::Call(address(0)?),
+        ("SE", [_, _], [Some(x), Some(y)]) => Instruction::SkipRegistersEqual(*x, *y),
+        ("SE", [_, _], [Some(x), None]) => Instruction::SkipEqual(*x, byte(1)?),
+        ("SNE", [_, _], [Some(x), Some(y)]) => Instruction::SkipRegistersNotEqual(*x, *y),
+        ("SNE", [_, _], [Some(x), None]) => Instruction::SkipNotEqual(*x, byte(1)?),
+        ("SAVE", [_, _], [Some(x), Some(y)]) => Instruction::SaveRange(*x, *y),
+        ("LOAD", [_, _], [Some(x), Some(y)]) => Instruction::LoadRange(*x, *y),
+        ("LD", [_, "DT"], [Some(x), _]) => Instruction::GetDelay(*x),
+        ("LD", [_, "K"], [Some(x), _]) => Instruction::WaitKey(*x),
+        ("LD", [_, "[I]"], [Some(x), _]) => Instruction::Load(*x),
+        ("LD", [_, "R"], [Some(x), _]) => Instruction::LoadFlags(*x),
+        ("LD", [_, _], [Some(x), Some(y)]) => Instruction::Copy(*x, *y),
+        ("LD", [_, _], [Some(x), None]) => Instruction::SetRegister(*x, byte(1)?),
+        ("LD", ["DT", _], [_, Some(x)]) => Instruction::SetDelay(*x),
+        ("LD", ["ST", _], [_, Some(x)]) => Instruction::SetSound(*x),
+        ("LD", ["F", _], [_, Some(x)]) => Instruction::Font(*x),
+        ("LD", ["HF", _], [_, Some(x)]) => Instruction::BigFont(*x),
+        ("LD", ["B", _], [_, Some(x)]) => Instruction::Bcd(*x),
+        ("LD", ["PITCH", _], [_, Some(x)]) => Instruction::SetPitch(*x),
+        ("LD", ["[I]", _], [_, Some(x)]) => Instruction::Store(*x),
+        ("LD", ["R", _], [_, Some(x)]) => Instruction::SaveFlags(*x),
+        ("LD", ["I", long], [_, None]) if long.starts_with("LONG ") => Instruction::SetILong(value(operands[1][5..].trim(), symbols, 0xFFFF, "address")?),
+        ("LD", ["I", _], [_, None]) => Instruction::SetI(address(1)?),
+        ("ADD", ["I", _], [_, Some(x)]) => Instruction::AddToI(*x),
+        ("ADD", [_, _], [Some(x), Some(y)]) => Instruction::Add(*x, *y),
+        ("ADD", [_, _], [Some(x), None]) => Instruction::AddToRegister(*x, byte(1)?),
+        ("OR", [_, _], [Some(x), Some(y)]) => Instruction::Or(*x, *y),
+        ("AND", [_, _], [Some(x), Some(y)]) => Instruction::And(*x, *y),
+        ("XOR", [_, _], [Some(x), Some(y)]) => Instruction::Xor(*x, *y),
+        ("SUB", [_, _], [Some(x), Some(y)]) => Instruction::Subtract(*x, *y),
+        ("SUBN", [_, _], [Some(x), Some(y)]) => Instruction::SubtractFrom(*x, *y),
+        ("SHR", [_], [Some(x)]) => Instruction::ShiftRight(*x, *x),
+        ("SHR", [_, _], [Some(x), Some(y)]) => Instruction::ShiftRight(*x, *y),
+        ("SHL", [_], [Some(x)]) => Instruction::ShiftLeft(*x, *x),
+        ("SHL", [_, _], [Some(x), Some(y)]) => Instruction::ShiftLeft(*x, *y),
+        ("RND", [_, _], [Some(x), None]) => Instruction::Random(*x, byte(1)?),
+        ("DRW", [_, _, _], [Some(x), Some(y), None]) => Instruction::Draw(*x, *y, nibble(2)?),
+        ("SKP", [_], [Some(x)]) => Instruction::SkipKeyPressed(*x),
+        ("SKNP", [_], [Some(x)]) => Instruction::SkipKeyNotPressed(*x),
+        ("PLANE", [_], [None]) => Instruction::SelectPlanes(value(&operands[0], symbols, 0x3, "plane mask")? as u8),
+        ("AUDIO", [], _) => Instruction::LoadAudio,
+        _ if is_mnemonic(mnemonic) => return Err(format!("invalid operands for {}: '{}'", mnemonic, operands.join(", "))),
+        _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+    };
+    Ok(instruction)
+}
+
+/// Returns true if `mnemonic` is a known instruction, to distinguish bad operands from typos.
+fn is_mnemonic(mnemonic: &str) -> bool {
+    ["CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE", "SAVE", "LOAD", "LD", "ADD",
+        "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "PLANE", "AUDIO"].contains(&mnemonic)
+}
@@ -0,0 +1,194 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use crate::chip8::CPU;
+
+/// Sample rate of rendered WAV files, in Hz.
+pub const WAV_SAMPLE_RATE: u32 = 44100;
+
+/// The shape of the buzzer's tone.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    /// A recorded sound, looped while the buzzer plays. Samples are between -1 and 1, recorded at `sample_rate` Hz.
+    Sampled { samples: Vec<f32>, sample_rate: u32 },
+}
+
+impl Waveform {
+    /// Looks up a waveform by name (`square`, `sine` or `triangle`), or failing that loads a WAV file to loop.
+    pub fn parse(text: &str) -> Result<Waveform, String> {
+        match text.to_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            _ if text.to_lowercase().ends_with(".wav") => Self::load(Path::new(text)),
+            _ => Err(format!("'{}' is not a waveform: expected one of square, sine, triangle, or a .wav file", text)),
+        }
+    }
+
+    /// Loads a WAV file as a sampled waveform. Channels are mixed down to mono.
+    pub fn load(path: &Path) -> Result<Waveform, String> {
+        let reader = hound::WavReader::open(path).map_err(|error| format!("Unable to read {}: {}", path.display(), error))?;
+        let spec = reader.spec();
+        let samples: Result<Vec<f32>, hound::Error> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.into_samples::<f32>().collect(),
+            hound::SampleFormat::Int => {
+                let full_scale = (1u32 << (spec.bits_per_sample - 1)) as f32;
+                reader.into_samples::<i32>().map(|sample| sample.map(|sample| sample as f32 / full_scale)).collect()
+            },
+        };
+        let samples = samples.map_err(|error| format!("Unable to read {}: {}", path.display(), error))?;
+        let samples: Vec<f32> = samples.chunks(spec.channels.max(1) as usize)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
+        if samples.is_empty() { return Err(format!("{} contains no samples", path.display())); }
+        Ok(Waveform::Sampled { samples, sample_rate: spec.sample_rate })
+    }
+}
+
+/// How the buzzer sounds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tone {
+    pub waveform: Waveform,
+    /// Pitch in Hz. Sampled waveforms are played at their recorded speed at 440Hz, and faster or slower otherwise.
+    pub frequency: f32,
+    /// Peak amplitude, between 0 and 1.
+    pub volume: f32,
+    /// Time to fade in when the buzzer starts, in seconds.
+    pub attack: f32,
+    /// Time to fade out when the buzzer stops, in seconds.
+    pub release: f32,
+}
+
+impl Default for Tone {
+    /// A 440Hz square wave at a quarter volume, with 5ms ramps so it starts and stops without clicking.
+    fn default() -> Self {
+        Tone { waveform: Waveform::Square, frequency: 440.0, volume: 0.25, attack: 0.005, release: 0.005 }
+    }
+}
+
+/// Generates the buzzer's output: the tone, or the XO-CHIP audio pattern buffer if the program has loaded one,
+/// faded in and out as the buzzer starts and stops.
+pub struct Synth{
+    tone: Tone,
+    sample_rate: f32,
+    phase: f32, // Position in the current period, from 0 to 1 (or in the pattern, in bits)
+    level: f32, // Envelope, from 0 (silent) to 1
+    gate: bool, // Whether the buzzer is on; the envelope ramps towards this
+    pattern: Option<[u8; 16]>,
+    pattern_rate: f32, // Playback rate of the pattern, in bits per second
+}
+
+impl Synth{
+    /// Creates a silent synthesiser producing samples at `sample_rate` Hz.
+    pub fn new(tone: Tone, sample_rate: u32) -> Self {
+        Synth { tone, sample_rate: sample_rate as f32, phase: 0.0, level: 0.0, gate: false, pattern: None, pattern_rate: 0.0 }
+    }
+
+    /// Follows the CPU's sound timer, audio pattern buffer and pitch.
+    pub fn update(&mut self, cpu: &CPU) {
+        self.gate = cpu.sound_timer() > 0;
+        self.pattern = cpu.audio_pattern();
+        self.pattern_rate = cpu.playback_rate();
+    }
+
+    /// Starts or stops the buzzer, regardless of the sound timer.
+    pub fn set_gate(&mut self, on: bool) { self.gate = on; }
+
+    /// Fills `out` with the next samples.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        let attack_step = 1.0 / (self.tone.attack * self.sample_rate).max(1.0);
+        let release_step = 1.0 / (self.tone.release * self.sample_rate).max(1.0);
+        for sample in out.iter_mut() {
+            self.level = if self.gate { (self.level + attack_step).min(1.0) } else { (self.level - release_step).max(0.0) };
+            *sample = if self.level > 0.0 { self.next_value() * self.tone.volume * self.level } else { 0.0 };
+        }
+    }
+
+    /// Returns the waveform's value at the current phase, between -1 and 1, and advances the phase by one sample.
+    fn next_value(&mut self) -> f32 {
+        // Plays the 128-bit pattern, most significant bit first. Phase counts bits.
+        if let Some(pattern) = self.pattern {
+            let bit = self.phase as usize % 128;
+            self.phase = (self.phase + self.pattern_rate / self.sample_rate) % 128.0;
+            return if (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1 { 1.0 } else { -1.0 };
+        }
+
+        // Otherwise phase counts periods of the waveform
+        let phase = self.phase % 1.0;
+        let (value, increment) = match &self.tone.waveform {
+            Waveform::Square => (if phase < 0.5 { 1.0 } else { -1.0 }, self.tone.frequency / self.sample_rate),
+            Waveform::Sine => ((phase * std::f32::consts::TAU).sin(), self.tone.frequency / self.sample_rate),
+            Waveform::Triangle => (1.0 - 4.0 * (phase - 0.5).abs(), self.tone.frequency / self.sample_rate),
+            // The whole recording is one period
+            Waveform::Sampled { samples, sample_rate } => (
+                samples[(phase * samples.len() as f32) as usize % samples.len()],
+                self.tone.frequency / 440.0 * *sample_rate as f32 / self.sample_rate / samples.len() as f32,
+            ),
+        };
+        self.phase = (phase + increment) % 1.0;
+        value
+    }
+}
+
+/// Renders the buzzer to a 16-bit mono WAV file, one 60th of a second per emulated frame, so the audio stays in sync
+/// with the game however fast it is emulated.
+///
+/// Write errors don't interrupt the game: the first is kept, rendering stops, and it is returned by [`WavRecorder::finish`].
+pub struct WavRecorder{
+    synth: Synth,
+    writer: hound::WavWriter<BufWriter<File>>,
+    frames: u64, // Frames rendered so far
+    error: Option<io::Error>,
+}
+
+impl WavRecorder{
+    /// Starts rendering the buzzer, with the given tone, to a WAV file.
+    pub fn create(path: &Path, tone: Tone) -> io::Result<WavRecorder> {
+        let spec = hound::WavSpec { channels: 1, sample_rate: WAV_SAMPLE_RATE, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let writer = hound::WavWriter::create(path, spec).map_err(wav_error)?;
+        Ok(WavRecorder { synth: Synth::new(tone, WAV_SAMPLE_RATE), writer, frames: 0, error: None })
+    }
+
+    /// Number of frames rendered so far.
+    pub fn frames(&self) -> u64 { self.frames }
+
+    /// Renders the next frame, with the buzzer following the CPU's sound timer.
+    pub fn push_frame(&mut self, cpu: &CPU) {
+        if self.error.is_some() { return; }
+        self.synth.update(cpu);
+        // Frame boundaries are rounded to the nearest sample, so sample rates that aren't a multiple of 60 don't drift
+        let samples = frame_start(self.frames + 1) - frame_start(self.frames);
+        let mut buffer = vec![0.0; samples as usize];
+        self.synth.fill(&mut buffer);
+        for sample in buffer {
+            if let Err(error) = self.writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16) {
+                self.error = Some(wav_error(error));
+                return;
+            }
+        }
+        self.frames += 1;
+    }
+
+    /// Stops rendering, completing the file. Returns the number of frames rendered, or the first write error.
+    pub fn finish(mut self) -> io::Result<u64> {
+        if let Some(error) = self.error.take() { return Err(error); }
+        self.writer.finalize().map_err(wav_error)?;
+        Ok(self.frames)
+    }
+}
+
+/// The sample a frame starts on, in rendered WAV files.
+fn frame_start(frame: u64) -> u64 {
+    (frame * WAV_SAMPLE_RATE as u64 + 30) / 60
+}
+
+/// Converts a WAV encoding error to an I/O error.
+fn wav_error(error: hound::Error) -> io::Error {
+    match error {
+        hound::Error::IoError(error) => error,
+        error => io::Error::new(io::ErrorKind::InvalidData, error),
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::chip8::{CPU, SCREEN_WIDTH, SCREEN_HEIGHT};
+use crate::palette::{Palette, Rgb};
+
+/// The file format gameplay is captured in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// An animated GIF, looping forever.
+    Gif,
+    /// Uncompressed YUV 4:4:4 video, which can be encoded with e.g. `ffmpeg -i clip.y4m clip.mp4`.
+    Y4m,
+    /// A directory of PNG files, one per frame, named `frame000000.png` onwards.
+    PngSequence,
+}
+
+impl CaptureFormat {
+    /// Chooses the format from a path's extension: `.gif`, `.y4m`, or otherwise a directory of PNG files.
+    pub fn from_path(path: &Path) -> CaptureFormat {
+        match path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).as_deref() {
+            Some("gif") => CaptureFormat::Gif,
+            Some("y4m") => CaptureFormat::Y4m,
+            _ => CaptureFormat::PngSequence,
+        }
+    }
+}
+
+/// Where captured frames are written.
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Y4m(BufWriter<File>),
+    PngSequence(PathBuf),
+}
+
+/// Captures gameplay at 60 frames per second, one frame for every 60Hz timer tick.
+/// Frames are a fixed size, so a game switching between low and high resolution keeps the same image size: each
+/// low-resolution pixel is drawn as 2x2 high-resolution pixels.
+///
+/// Write errors don't interrupt the game: the first is kept, capture stops, and it is returned by [`Capture::finish`].
+pub struct Capture{
+    output: Output,
+    scale: u32, // Image pixels per high-resolution CHIP-8 pixel
+    width: u32, // Size of each frame, in image pixels
+    height: u32,
+    global_colours: [Rgb; 4], // Colours of the GIF's global palette; frames in other colours carry their own
+    frames: u64, // Frames captured so far
+    pending: Option<(Vec<u8>, [Rgb; 4], u64)>, // GIF frame not yet written, as its length isn't known, and the frame it started on
+    error: Option<io::Error>,
+}
+
+/// Width of a captured frame, in high-resolution CHIP-8 pixels.
+const CAPTURE_WIDTH: usize = SCREEN_WIDTH;
+/// Height of a captured frame, in high-resolution CHIP-8 pixels.
+const CAPTURE_HEIGHT: usize = SCREEN_HEIGHT;
+
+impl Capture{
+    /// Starts capturing to `path`, in the format given by its extension (see [`CaptureFormat::from_path`]).
+    /// Each high-resolution pixel is `scale` (at least 1) image pixels across; `palette` is the initial palette.
+    pub fn create(path: &Path, scale: u32, palette: &Palette) -> io::Result<Capture> {
+        let scale = scale.max(1);
+        let (width, height) = (CAPTURE_WIDTH as u32 * scale, CAPTURE_HEIGHT as u32 * scale);
+        let global_colours = colours(palette);
+        let output = match CaptureFormat::from_path(path) {
+            CaptureFormat::Gif => {
+                let (width, height) = (u16::try_from(width), u16::try_from(height));
+                let (Ok(width), Ok(height)) = (width, height) else {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "capture scale is too large for a GIF"));
+                };
+                let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), width, height, global_colours.as_flattened())
+                    .map_err(gif_error)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+                Output::Gif(encoder)
+            },
+            CaptureFormat::Y4m => {
+                let mut out = BufWriter::new(File::create(path)?);
+                writeln!(out, "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444", width, height)?;
+                Output::Y4m(out)
+            },
+            CaptureFormat::PngSequence => {
+                fs::create_dir_all(path)?;
+                Output::PngSequence(path.to_path_buf())
+            },
+        };
+        Ok(Capture { output, scale, width, height, global_colours, frames: 0, pending: None, error: None })
+    }
+
+    /// Number of frames captured so far.
+    pub fn frames(&self) -> u64 { self.frames }
+
+    /// Captures the current screen, in the given palette, as the next frame.
+    pub fn push_frame(&mut self, cpu: &CPU, palette: &Palette) {
+        if self.error.is_some() { return; }
+        let pixels = self.render(cpu);
+        let colours = colours(palette);
+        if let Err(error) = self.write_frame(pixels, colours) { self.error = Some(error); }
+        self.frames += 1;
+    }
+
+    /// Stops capturing, writing any remaining data. Returns the number of frames captured, or the first write error.
+    pub fn finish(mut self) -> io::Result<u64> {
+        if let Some(error) = self.error.take() { return Err(error); }
+        self.flush_gif_frame()?;
+        match self.output {
+            Output::Gif(encoder) => { encoder.into_inner()?.flush()?; },
+            Output::Y4m(mut out) => out.flush()?,
+            Output::PngSequence(_) => { },
+        }
+        Ok(self.frames)
+    }
+
+    /// Renders the screen as palette indices (plane bitmasks), at a fixed high-resolution size.
+    fn render(&self, cpu: &CPU) -> Vec<u8> {
+        let scale = self.scale as usize;
+        // Low-resolution pixels are twice the size of high-resolution ones
+        let pixel_size = scale * CAPTURE_WIDTH / cpu.screen_width();
+        let mut pixels = Vec::with_capacity(CAPTURE_WIDTH * CAPTURE_HEIGHT * scale * scale);
+        for row in cpu.screen.iter().take(cpu.screen_height()) {
+            let line: Vec<u8> = row.iter().take(cpu.screen_width())
+                .flat_map(|pixel| [pixel & 0b11].repeat(pixel_size))
+                .collect();
+            for _ in 0..pixel_size { pixels.extend_from_slice(&line); }
+        }
+        pixels
+    }
+
+    /// Writes a frame of palette indices in the given colours.
+    fn write_frame(&mut self, pixels: Vec<u8>, colours: [Rgb; 4]) -> io::Result<()> {
+        let frame = self.frames;
+        match &mut self.output {
+            // GIF frames last until the screen next changes, so identical frames are merged
+            Output::Gif(_) => {
+                if self.pending.as_ref().is_some_and(|(pending, pending_colours, _)| *pending == pixels && *pending_colours == colours) {
+                    return Ok(());
+                }
+                self.flush_gif_frame()?;
+                self.pending = Some((pixels, colours, frame));
+            },
+            Output::Y4m(out) => {
+                let yuv = colours.map(to_yuv);
+                writeln!(out, "FRAME")?;
+                let planes = [0, 1, 2].map(|channel| pixels.iter().map(|pixel| yuv[*pixel as usize][channel]).collect::<Vec<u8>>());
+                for plane in planes { out.write_all(&plane)?; }
+            },
+            Output::PngSequence(directory) => {
+                let mut out = BufWriter::new(File::create(directory.join(format!("frame{:06}.png", frame)))?);
+                let mut encoder = png::Encoder::new(&mut out, self.width, self.height);
+                encoder.set_color(png::ColorType::Indexed);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_palette(colours.as_flattened().to_vec());
+                let mut writer = encoder.write_header()?;
+                writer.write_image_data(&pixels)?;
+                writer.finish()?;
+                out.flush()?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Writes the pending GIF frame, which lasts until the current frame.
+    fn flush_gif_frame(&mut self) -> io::Result<()> {
+        let (Output::Gif(encoder), Some((pixels, colours, start))) = (&mut self.output, self.pending.take()) else { return Ok(()) };
+        // The size was checked to fit in 16 bits when the encoder was created
+        let mut frame = gif::Frame::from_indexed_pixels(self.width as u16, self.height as u16, pixels, None);
+        // GIF delays are in hundredths of a second, so frame boundaries are rounded to keep exact 60fps timing overall
+        frame.delay = (gif_time(self.frames) - gif_time(start)) as u16;
+        if colours != self.global_colours { frame.palette = Some(colours.as_flattened().to_vec()); }
+        encoder.write_frame(&frame).map_err(gif_error)
+    }
+}
+
+/// The colours of a palette, indexed by plane bitmask.
+fn colours(palette: &Palette) -> [Rgb; 4] {
+    [0, 1, 2, 3].map(|planes| palette.colour(planes))
+}
+
+/// The time at which a frame starts, in hundredths of a second, rounded to the nearest.
+fn gif_time(frame: u64) -> u64 {
+    (frame * 100 + 30) / 60
+}
+
+/// Converts a colour to limited-range BT.601 YUV, as expected by most video encoders.
+fn to_yuv([r, g, b]: Rgb) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let u = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let v = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    [y, u, v].map(|channel| channel.round() as u8)
+}
+
+/// Converts a GIF encoding error to an I/O error.
+fn gif_error(error: gif::EncodingError) -> io::Error {
+    match error {
+        gif::EncodingError::Io(error) => error,
+        error => io::Error::new(io::ErrorKind::InvalidData, error),
+    }
+}
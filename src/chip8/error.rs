@@ -0,0 +1,69 @@
+use std::fmt;
+
+/// The result of successfully executing one instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction was executed, and the CPU is ready for the next one.
+    Executed,
+    /// The program has exited (SCHIP `00FD`), so nothing was executed.
+    Exited,
+    /// A sprite was drawn with the display-wait quirk on. The instruction was executed, but no more should run until
+    /// the next frame (the next call to [`CPU::tick`](super::CPU::tick)).
+    DisplayWait,
+}
+
+/// A fault raised by the CPU. Each variant records the address (`pc`) and value of the offending instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    /// The instruction does not decode to any known opcode.
+    IllegalOpcode { pc: u16, opcode: u16 },
+    /// A subroutine call was made with all 16 stack entries already in use.
+    StackOverflow { pc: u16, opcode: u16 },
+    /// A return was made with an empty stack.
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// The instruction tried to access memory past the end of the address space.
+    MemoryOutOfBounds { pc: u16, opcode: u16, address: usize },
+    /// The ROM does not fit into memory after the reserved interpreter area.
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::IllegalOpcode { pc, opcode } =>
+                write!(f, "illegal instruction 0x{:04X} at 0x{:03X}", opcode, pc),
+            CpuError::StackOverflow { pc, opcode } =>
+                write!(f, "stack overflow by instruction 0x{:04X} at 0x{:03X}", opcode, pc),
+            CpuError::StackUnderflow { pc, opcode } =>
+                write!(f, "stack underflow by instruction 0x{:04X} at 0x{:03X}", opcode, pc),
+            CpuError::MemoryOutOfBounds { pc, opcode, address } =>
+                write!(f, "out-of-bounds memory access to 0x{:X} by instruction 0x{:04X} at 0x{:03X}", address, opcode, pc),
+            CpuError::RomTooLarge { size, max } =>
+                write!(f, "ROM is {} bytes, but at most {} bytes can be loaded", size, max),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+/// A snapshot that could not be restored by [`CPU::restore`](super::CPU::restore).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The snapshot is not the size this version of the interpreter writes.
+    WrongSize { expected: usize, found: usize },
+    /// A field of the snapshot holds a value the CPU cannot be in.
+    InvalidField(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::WrongSize { expected, found } =>
+                write!(f, "snapshot is {} bytes, expected {}", found, expected),
+            SnapshotError::InvalidField(field) =>
+                write!(f, "snapshot has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
@@ -0,0 +1,10 @@
+/// Hashes a block of bytes using 64-bit FNV-1a. Used to identify ROMs and to compare memory contents.
+/// This is not a cryptographic hash, but it is stable across platforms and builds.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
@@ -0,0 +1,198 @@
+/// A decoded CHIP-8, SCHIP or XO-CHIP instruction, as understood by `CPU::step`.
+/// Used by the disassembler and assembler; register operands are indices (0 through F).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    ScrollDown(u8), // 00Cn
+    ScrollUp(u8), // 00Dn
+    Clear, // 00E0
+    Return, // 00EE
+    ScrollRight, // 00FB
+    ScrollLeft, // 00FC
+    Exit, // 00FD
+    Lores, // 00FE
+    Hires, // 00FF
+    Jump(u16), // 1nnn
+    Call(u16), // 2nnn
+    SkipEqual(u8, u8), // 3xkk
+    SkipNotEqual(u8, u8), // 4xkk
+    SkipRegistersEqual(u8, u8), // 5xy0
+    SaveRange(u8, u8), // 5xy2
+    LoadRange(u8, u8), // 5xy3
+    SetRegister(u8, u8), // 6xkk
+    AddToRegister(u8, u8), // 7xkk
+    Copy(u8, u8), // 8xy0
+    Or(u8, u8), // 8xy1
+    And(u8, u8), // 8xy2
+    Xor(u8, u8), // 8xy3
+    Add(u8, u8), // 8xy4
+    Subtract(u8, u8), // 8xy5
+    ShiftRight(u8, u8), // 8xy6
+    SubtractFrom(u8, u8), // 8xy7
+    ShiftLeft(u8, u8), // 8xyE
+    SkipRegistersNotEqual(u8, u8), // 9xy0
+    SetI(u16), // Annn
+    JumpOffset(u16), // Bnnn
+    Random(u8, u8), // Cxkk
+    Draw(u8, u8, u8), // Dxyn
+    SkipKeyPressed(u8), // Ex9E
+    SkipKeyNotPressed(u8), // ExA1
+    SetILong(u16), // F000 nnnn
+    SelectPlanes(u8), // Fn01
+    LoadAudio, // F002
+    GetDelay(u8), // Fx07
+    WaitKey(u8), // Fx0A
+    SetDelay(u8), // Fx15
+    SetSound(u8), // Fx18
+    AddToI(u8), // Fx1E
+    Font(u8), // Fx29
+    BigFont(u8), // Fx30
+    Bcd(u8), // Fx33
+    SetPitch(u8), // Fx3A
+    Store(u8), // Fx55
+    Load(u8), // Fx65
+    SaveFlags(u8), // Fx75
+    LoadFlags(u8), // Fx85
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `bytes`. Returns `None` if there aren't enough bytes,
+    /// or if they don't form an instruction the CPU would execute.
+    pub fn decode(bytes: &[u8]) -> Option<Instruction> {
+        let opcode = (*bytes.first()? as u16) << 8 | *bytes.get(1)? as u16;
+        let (digit1, x, y, n) = (opcode >> 12, (opcode >> 8) as u8 & 0xF, (opcode >> 4) as u8 & 0xF, opcode as u8 & 0xF);
+        let (nnn, kk) = (opcode & 0xFFF, opcode as u8);
+
+        let instruction = match (digit1, x, y, n) {
+            (0x0, 0x0, 0xC, _) => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xD, _) => Instruction::ScrollUp(n),
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Clear,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Return,
+            (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::Lores,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::Hires,
+            (0x1, _, _, _) => Instruction::Jump(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, _, _, _) => Instruction::SkipEqual(x, kk),
+            (0x4, _, _, _) => Instruction::SkipNotEqual(x, kk),
+            (0x5, _, _, 0x0) => Instruction::SkipRegistersEqual(x, y),
+            (0x5, _, _, 0x2) => Instruction::SaveRange(x, y),
+            (0x5, _, _, 0x3) => Instruction::LoadRange(x, y),
+            (0x6, _, _, _) => Instruction::SetRegister(x, kk),
+            (0x7, _, _, _) => Instruction::AddToRegister(x, kk),
+            (0x8, _, _, 0x0) => Instruction::Copy(x, y),
+            (0x8, _, _, 0x1) => Instruction::Or(x, y),
+            (0x8, _, _, 0x2) => Instruction::And(x, y),
+            (0x8, _, _, 0x3) => Instruction::Xor(x, y),
+            (0x8, _, _, 0x4) => Instruction::Add(x, y),
+            (0x8, _, _, 0x5) => Instruction::Subtract(x, y),
+            (0x8, _, _, 0x6) => Instruction::ShiftRight(x, y),
+            (0x8, _, _, 0x7) => Instruction::SubtractFrom(x, y),
+            (0x8, _, _, 0xE) => Instruction::ShiftLeft(x, y),
+            (0x9, _, _, 0x0) => Instruction::SkipRegistersNotEqual(x, y),
+            (0xA, _, _, _) => Instruction::SetI(nnn),
+            (0xB, _, _, _) => Instruction::JumpOffset(nnn),
+            (0xC, _, _, _) => Instruction::Random(x, kk),
+            (0xD, _, _, _) => Instruction::Draw(x, y, n),
+            (0xE, _, 0x9, 0xE) => Instruction::SkipKeyPressed(x),
+            (0xE, _, 0xA, 0x1) => Instruction::SkipKeyNotPressed(x),
+            (0xF, 0x0, 0x0, 0x0) => Instruction::SetILong((*bytes.get(2)? as u16) << 8 | *bytes.get(3)? as u16),
+            (0xF, _, 0x0, 0x1) => Instruction::SelectPlanes(x),
+            (0xF, 0x0, 0x0, 0x2) => Instruction::LoadAudio,
+            (0xF, _, 0x0, 0x7) => Instruction::GetDelay(x),
+            (0xF, _, 0x0, 0xA) => Instruction::WaitKey(x),
+            (0xF, _, 0x1, 0x5) => Instruction::SetDelay(x),
+            (0xF, _, 0x1, 0x8) => Instruction::SetSound(x),
+            (0xF, _, 0x1, 0xE) => Instruction::AddToI(x),
+            (0xF, _, 0x2, 0x9) => Instruction::Font(x),
+            (0xF, _, 0x3, 0x0) => Instruction::BigFont(x),
+            (0xF, _, 0x3, 0x3) => Instruction::Bcd(x),
+            (0xF, _, 0x3, 0xA) => Instruction::SetPitch(x),
+            (0xF, _, 0x5, 0x5) => Instruction::Store(x),
+            (0xF, _, 0x6, 0x5) => Instruction::Load(x),
+            (0xF, _, 0x7, 0x5) => Instruction::SaveFlags(x),
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags(x),
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Encodes the instruction as bytes: two, or four for `F000 nnnn`.
+    pub fn encode(&self) -> Vec<u8> {
+        let xy = |high: u16, x: u8, y: u8, low: u16| high << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | low;
+        let xkk = |high: u16, x: u8, kk: u8| high << 12 | (x as u16 & 0xF) << 8 | kk as u16;
+        let fx = |x: u8, low: u16| 0xF000 | (x as u16 & 0xF) << 8 | low;
+
+        let opcode = match *self {
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Lores => 0x00FE,
+            Instruction::Hires => 0x00FF,
+            Instruction::Jump(nnn) => 0x1000 | (nnn & 0xFFF),
+            Instruction::Call(nnn) => 0x2000 | (nnn & 0xFFF),
+            Instruction::SkipEqual(x, kk) => xkk(0x3, x, kk),
+            Instruction::SkipNotEqual(x, kk) => xkk(0x4, x, kk),
+            Instruction::SkipRegistersEqual(x, y) => xy(0x5, x, y, 0x0),
+            Instruction::SaveRange(x, y) => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange(x, y) => xy(0x5, x, y, 0x3),
+            Instruction::SetRegister(x, kk) => xkk(0x6, x, kk),
+            Instruction::AddToRegister(x, kk) => xkk(0x7, x, kk),
+            Instruction::Copy(x, y) => xy(0x8, x, y, 0x0),
+            Instruction::Or(x, y) => xy(0x8, x, y, 0x1),
+            Instruction::And(x, y) => xy(0x8, x, y, 0x2),
+            Instruction::Xor(x, y) => xy(0x8, x, y, 0x3),
+            Instruction::Add(x, y) => xy(0x8, x, y, 0x4),
+            Instruction::Subtract(x, y) => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xy(0x8, x, y, 0x6),
+            Instruction::SubtractFrom(x, y) => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xy(0x8, x, y, 0xE),
+            Instruction::SkipRegistersNotEqual(x, y) => xy(0x9, x, y, 0x0),
+            Instruction::SetI(nnn) => 0xA000 | (nnn & 0xFFF),
+            Instruction::JumpOffset(nnn) => 0xB000 | (nnn & 0xFFF),
+            Instruction::Random(x, kk) => xkk(0xC, x, kk),
+            Instruction::Draw(x, y, n) => xy(0xD, x, y, n as u16 & 0xF),
+            Instruction::SkipKeyPressed(x) => xkk(0xE, x, 0x9E),
+            Instruction::SkipKeyNotPressed(x) => xkk(0xE, x, 0xA1),
+            Instruction::SetILong(nnnn) => return vec![0xF0, 0x00, (nnnn >> 8) as u8, nnnn as u8],
+            Instruction::SelectPlanes(n) => fx(n, 0x01),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::GetDelay(x) => fx(x, 0x07),
+            Instruction::WaitKey(x) => fx(x, 0x0A),
+            Instruction::SetDelay(x) => fx(x, 0x15),
+            Instruction::SetSound(x) => fx(x, 0x18),
+            Instruction::AddToI(x) => fx(x, 0x1E),
+            Instruction::Font(x) => fx(x, 0x29),
+            Instruction::BigFont(x) => fx(x, 0x30),
+            Instruction::Bcd(x) => fx(x, 0x33),
+            Instruction::SetPitch(x) => fx(x, 0x3A),
+            Instruction::Store(x) => fx(x, 0x55),
+            Instruction::Load(x) => fx(x, 0x65),
+            Instruction::SaveFlags(x) => fx(x, 0x75),
+            Instruction::LoadFlags(x) => fx(x, 0x85),
+        };
+        vec![(opcode >> 8) as u8, opcode as u8]
+    }
+
+    /// Length of the instruction in bytes: two, or four for `F000 nnnn`.
+    pub fn size(&self) -> usize {
+        if let Instruction::SetILong(_) = self { 4 } else { 2 }
+    }
+
+    /// Returns true if execution can fall through to the next instruction.
+    /// False for jumps, returns and exit.
+    pub fn falls_through(&self) -> bool {
+        !matches!(self, Instruction::Jump(_) | Instruction::JumpOffset(_) | Instruction::Return | Instruction::Exit)
+    }
+
+    /// Returns true if the instruction may skip the one after it.
+    pub fn is_skip(&self) -> bool {
+        matches!(self, Instruction::SkipEqual(..) | Instruction::SkipNotEqual(..) | Instruction::SkipRegistersEqual(..)
+            | Instruction::SkipRegistersNotEqual(..) | Instruction::SkipKeyPressed(_) | Instruction::SkipKeyNotPressed(_))
+    }
+}
@@ -1,27 +1,72 @@
 // bobbin_bits library used to eliminate redundant masking/range checking on function parameters.
 use bobbin_bits::*;
-use rand::Rng;
+
+mod error;
+mod hash;
+mod instruction;
+mod quirks;
+mod random;
+mod snapshot;
+mod timing;
+#[cfg(test)]
+mod tests;
+pub use error::{CpuError, SnapshotError, StepOutcome};
+pub use hash::fnv1a;
+pub use instruction::Instruction;
+pub use quirks::{Quirks, IndexIncrement};
+pub use random::{RandomKind, RandomSource, VIP_INTERPRETER_SIZE};
+pub use snapshot::SNAPSHOT_SIZE;
+pub use timing::{VIP_CYCLES_PER_FRAME, VIP_EXECUTION_CYCLES};
 
 /// The CHIP-8 interpreter itself. Encapsulates memory, registers, the screen, and keyboard.
 #[derive(Clone)]
 #[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU{
-    memory: [u8; 4096], // 4KB of addressable memory
+    memory: Vec<u8>, // 64KB of addressable memory (XO-CHIP); original CHIP-8 programs only use the first 4KB
     registers: [u8; 16], // 16 general-purpose registers, V0 through VF
     i: u16, // Special register
     dt: u8, // 8-bit delay timer
-    pub st: u8, // 8-bit sound timer
+    st: u8, // 8-bit sound timer
     pc: u16, // 16-bit program counter
     sp: u8, // 8-bit stack pointer
     stack: [u16; 16], // 16 element stack
-    pub screen: [[bool; 64]; 32], // 64x32 display, represented as 2D array of booleans
-                                  // True indicates pixel should be lit, false indicates otherwise
+    pub screen: [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT], // 128x64 display, represented as 2D array of plane bitmasks
+                                                     // Bit 0 is set if the pixel is lit on plane 1, bit 1 if lit on plane 2 (XO-CHIP)
+                                                     // In low-resolution mode, only the top-left 64x32 pixels are used
+    planes: u8, // Bitmask of the planes affected by drawing, clearing and scrolling (XO-CHIP)
+    audio_pattern: Option<[u8; 16]>, // 128-bit audio pattern buffer, if one has been loaded (XO-CHIP)
+    pitch: u8, // Playback pitch of the audio pattern buffer (XO-CHIP)
+    pub hires: bool, // SCHIP high-resolution (128x64) mode
+    exited: bool, // Set once the program has executed the SCHIP exit instruction
+    rpl: [u8; 16], // SCHIP "RPL user flags", persisted independently of the registers
     keyboard: [bool; 16], // 16 character keyboard, labelled 0 through F
                           // True indicates the character is being pressed, false indicates otherwise
+    key_wait: Option<KeyWait>, // Progress of the Fx0A instruction being executed, if it is waiting for a key
+    quirks: Quirks, // Platform-specific behaviour of ambiguous instructions
+    rng: RandomSource, // Source of the random values returned by Cxkk
+    instruction_pc: u16, // Address of the instruction currently being executed, for error reporting
+    opcode: u16, // The instruction currently being executed, for error reporting
     saved_state: Box<Option<CPU>>, // A single save state is stored as well.
                                    // To avoid infinite recursion, it is placed inside a Box
 }
 
+/// Progress of an `Fx0A` instruction waiting for a key. Keys change between frames, while the instruction is repeated,
+/// so this is kept across frames to spot keys being pressed and released.
+#[derive(Clone, Copy, Debug)]
+struct KeyWait {
+    held: u16, // Keys held since the wait began, as a mask; these only count once released and pressed again
+    pressed: Option<u8>, // The key pressed, if waiting for it to be released
+}
+
+/// Size of addressable memory, in bytes.
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// Width of the display in high-resolution mode. Low-resolution mode is half this.
+pub const SCREEN_WIDTH: usize = 128;
+/// Height of the display in high-resolution mode. Low-resolution mode is half this.
+pub const SCREEN_HEIGHT: usize = 64;
+
 /// Default font for CHIP-8 games, loaded into memory at address 0x0.
 /// This consists of sixteen 8x5 sprites.
 const FONT: [u8; 80] = [
@@ -43,11 +88,36 @@ const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+/// Large font for SCHIP games, loaded into memory directly after the default font (at address 0x50).
+/// This consists of sixteen 8x10 sprites.
+const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+/// Address of the large font in memory.
+const BIG_FONT_ADDRESS: usize = 0x50;
+
 impl CPU{
     /// Instantiates a CHIP-8 compatable CPU, with font data copied into memory.
-    pub fn new() -> Self{
+    /// `quirks` selects how ambiguous instructions behave; see [`Quirks`] for the available presets.
+    pub fn new(quirks: Quirks) -> Self{
         let mut new_cpu: CPU = Self{
-            memory: [0; 4096],
+            memory: vec![0; MEMORY_SIZE],
             registers: [0; 16],
             i: 0,
             dt: 0,
@@ -55,26 +125,54 @@ impl CPU{
             pc: 0x200,
             sp: 0,
             stack: [0; 16],
-            screen: [[false; 64]; 32],
+            screen: [[0; SCREEN_WIDTH]; SCREEN_HEIGHT],
+            planes: 1,
+            audio_pattern: None,
+            pitch: 64,
+            hires: false,
+            exited: false,
+            rpl: [0; 16],
             keyboard: [false; 16],
+            key_wait: None,
+            quirks,
+            rng: RandomSource::seeded(rand::random()),
+            instruction_pc: 0x200,
+            opcode: 0,
             saved_state: Box::new(None),
         };
 
         new_cpu.memory[..0x50].copy_from_slice(&FONT);
+        new_cpu.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
 
         new_cpu
     }
 
-    /// Pushes a new value onto the stack.
-    fn push(&mut self, val: u16){
+    /// Pushes a new value onto the stack. Fails if the stack is full.
+    fn push(&mut self, val: u16) -> Result<(), CpuError> {
+        if self.sp as usize >= self.stack.len() {
+            return Err(CpuError::StackOverflow { pc: self.instruction_pc, opcode: self.opcode });
+        }
         self.stack[self.sp as usize] = val;
         self.sp += 1;
+        Ok(())
     }
 
-    /// Pops the value from the top of the stack and returns it.
-    fn pop(&mut self) -> u16 {
+    /// Pops the value from the top of the stack and returns it. Fails if the stack is empty.
+    fn pop(&mut self) -> Result<u16, CpuError> {
+        if self.sp == 0 {
+            return Err(CpuError::StackUnderflow { pc: self.instruction_pc, opcode: self.opcode });
+        }
         self.sp -= 1;
-        self.stack[self.sp as usize]
+        Ok(self.stack[self.sp as usize])
+    }
+
+    /// Checks that the `length` bytes starting at `address` all lie within memory (4KB or 64KB, depending on the quirks).
+    fn check_memory(&self, address: usize, length: usize) -> Result<(), CpuError> {
+        let size = self.quirks.memory_size();
+        if address + length > size {
+            return Err(CpuError::MemoryOutOfBounds { pc: self.instruction_pc, opcode: self.opcode, address: address.max(size) });
+        }
+        Ok(())
     }
 
     /// Joins three 4-byte numbers into one 12-byte number.
@@ -87,9 +185,16 @@ impl CPU{
     }
 
     /// Loads supplied ROM data into memory, starting at offset 0x200.
-    pub fn load(&mut self, rom: &[u8]) {
-        let end = 0x200 as usize + rom.len();
-        self.memory[0x200 as usize..end].copy_from_slice(&rom);
+    /// Fails if the ROM is too large to fit into the remaining memory: 3584 bytes, or 65024 bytes with the
+    /// `large_memory` quirk (XO-CHIP).
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), CpuError> {
+        let max = self.quirks.memory_size() - 0x200;
+        if rom.len() > max {
+            return Err(CpuError::RomTooLarge { size: rom.len(), max });
+        }
+        let end = 0x200 + rom.len();
+        self.memory[0x200..end].copy_from_slice(rom);
+        Ok(())
     }
 
     /// Update the status of a given key. Must be called every frame by the graphics layer.
@@ -98,30 +203,165 @@ impl CPU{
         self.keyboard[key as usize] = state;
     }
 
-    /// Performs one fetch-decode-execute cycle.
-    pub fn step(&mut self) {
+    /// Returns true if the given key is currently pressed.
+    pub fn is_key_pressed(&self, key: U4) -> bool {
+        self.keyboard[key as usize]
+    }
+
+    /// The pressed keys, as a bitmask with bit n set if key n is pressed.
+    pub fn key_mask(&self) -> u16 {
+        (0..16).filter(|key| self.keyboard[*key]).fold(0, |mask, key| mask | 1 << key)
+    }
+
+    /// Sets every key at once from a bitmask, with bit n set if key n is pressed.
+    pub fn set_key_mask(&mut self, mask: u16) {
+        for (key, pressed) in self.keyboard.iter_mut().enumerate() {
+            *pressed = mask & (1 << key) != 0;
+        }
+    }
+
+    /// Seeds the random number generator used by `Cxkk`, replacing any other source with [`RandomSource::Seeded`].
+    /// The generator is seeded randomly when the CPU is created, so this is only needed for reproducible runs.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = RandomSource::seeded(seed);
+    }
+
+    /// Replaces the source of the values returned by `Cxkk`, e.g. with a scripted sequence for tests.
+    pub fn set_random_source(&mut self, source: RandomSource) {
+        self.rng = source;
+    }
+
+    /// The source of the values returned by `Cxkk`.
+    pub fn random_source(&self) -> &RandomSource {
+        &self.rng
+    }
+
+    /// The general-purpose registers, V0 through VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    /// The value of the I register.
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    /// The program counter: the address of the next instruction to be executed.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// The stack pointer: the number of entries currently on the stack.
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// The entire stack. Only the first `sp()` entries are in use.
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+
+    /// The value of the delay timer.
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    /// The value of the sound timer. The buzzer should sound while this is non-zero.
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
+
+    /// The entire address space, including the font data at the start of memory.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The quirks this CPU was created with.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Returns true once the program has exited via the SCHIP `00FD` instruction.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Width of the display in the current resolution mode, in pixels.
+    pub fn screen_width(&self) -> usize {
+        if self.hires { SCREEN_WIDTH } else { SCREEN_WIDTH / 2 }
+    }
+
+    /// Height of the display in the current resolution mode, in pixels.
+    pub fn screen_height(&self) -> usize {
+        if self.hires { SCREEN_HEIGHT } else { SCREEN_HEIGHT / 2 }
+    }
+
+    /// Returns the audio pattern buffer loaded by `F002`, or `None` if the program hasn't loaded one.
+    /// Each of the 128 bits is one sample: 1 for high, 0 for low, played from the most significant bit of the first byte.
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
+
+    /// The rate, in bits per second, at which the audio pattern buffer should be played back.
+    /// This is 4000Hz at the default pitch of 64, doubling every 48 steps.
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Performs one fetch-decode-execute cycle. Does nothing once the program has exited.
+    /// If the instruction faults, the program counter is left pointing at it and the error is returned.
+    pub fn step(&mut self) -> Result<StepOutcome, CpuError> {
+        if self.exited { return Ok(StepOutcome::Exited); }
+
+        self.instruction_pc = self.pc;
+        self.opcode = 0;
+
         // Fetch bytes (PC, PC + 1)
+        self.check_memory(self.pc as usize, 2)?;
         let byte1 = self.memory[self.pc as usize];
-        let byte2 = self.memory[(self.pc + 1) as usize];
+        let byte2 = self.memory[self.pc as usize + 1];
+        self.opcode = (byte1 as u16) << 8 | byte2 as u16;
 
+        // Increment PC
+        self.pc = self.pc.wrapping_add(2);
+
+        match self.execute(byte1, byte2) {
+            // With the display-wait quirk, nothing more runs this frame after a draw
+            Ok(()) if self.quirks.display_wait && byte1 >> 4 == 0xD => Ok(StepOutcome::DisplayWait),
+            Ok(()) => Ok(StepOutcome::Executed),
+            Err(error) => {
+                self.pc = self.instruction_pc;
+                Err(error)
+            },
+        }
+    }
+
+    /// Decodes and executes a fetched instruction.
+    fn execute(&mut self, byte1: u8, byte2: u8) -> Result<(), CpuError> {
         // Parse into four hex digits
         let digit1: U4 = (byte1 >> 4).into();
         let digit2: U4 = (byte1 & 0xF).into();
         let digit3: U4 = (byte2 >> 4).into();
         let digit4: U4 = (byte2 & 0xF).into();
 
-        // Increment PC
-        self.pc += 2;
-
         // Decode and execute instruction
         match(digit1, digit2, digit3, digit4){
+            (U4::B0000, U4::B0000, U4::B1100, _) => self.scroll_down(digit4), // 00Cn
+            (U4::B0000, U4::B0000, U4::B1101, _) => self.scroll_up(digit4), // 00Dn
             (U4::B0000, U4::B0000, U4::B1110, U4::B0000) => self.clear(), // 00E0
-            (U4::B0000, U4::B0000, U4::B1110, U4::B1110) => self.ret(), // 00EE
+            (U4::B0000, U4::B0000, U4::B1110, U4::B1110) => self.ret()?, // 00EE
+            (U4::B0000, U4::B0000, U4::B1111, U4::B1011) => self.scroll_right(), // 00FB
+            (U4::B0000, U4::B0000, U4::B1111, U4::B1100) => self.scroll_left(), // 00FC
+            (U4::B0000, U4::B0000, U4::B1111, U4::B1101) => self.exit(), // 00FD
+            (U4::B0000, U4::B0000, U4::B1111, U4::B1110) => self.set_resolution(false), // 00FE
+            (U4::B0000, U4::B0000, U4::B1111, U4::B1111) => self.set_resolution(true), // 00FF
             (U4::B0001, _, _, _) => self.jump(Self::concat_digits(digit2, digit3, digit4)), // 1nnn
-            (U4::B0010, _, _, _) => self.call(Self::concat_digits(digit2, digit3, digit4)), // 2nnn
+            (U4::B0010, _, _, _) => self.call(Self::concat_digits(digit2, digit3, digit4))?, // 2nnn
             (U4::B0011, _, _, _) => self.skip_if_equal(digit2, byte2), // 3xkk
             (U4::B0100, _, _, _) => self.skip_if_not_equal(digit2, byte2), // 4xkk
             (U4::B0101, _, _, U4::B0000) => self.skip_if_registers_equal(digit2, digit3), // 5xy0
+            (U4::B0101, _, _, U4::B0010) => self.save_register_range(digit2, digit3)?, // 5xy2
+            (U4::B0101, _, _, U4::B0011) => self.load_register_range(digit2, digit3)?, // 5xy3
             (U4::B0110, _, _, _) => self.copy_into_register(digit2, byte2), // 6xkk
             (U4::B0111, _, _, _) => self.increment_register(digit2, byte2), // 7xkk
             (U4::B1000, _, _, U4::B0000) => self.copy_register(digit2, digit3), // 8xy0
@@ -130,27 +370,36 @@ impl CPU{
             (U4::B1000, _, _, U4::B0011) => self.xor_registers(digit2, digit3), // 8xy3
             (U4::B1000, _, _, U4::B0100) => self.add_registers(digit2, digit3), // 8xy4
             (U4::B1000, _, _, U4::B0101) => self.subtract_registers(digit2, digit3), // 8xy5
-            (U4::B1000, _, _, U4::B0110) => self.right_shift_register(digit2), // 8xy6
+            (U4::B1000, _, _, U4::B0110) => self.right_shift_register(digit2, digit3), // 8xy6
             (U4::B1000, _, _, U4::B0111) => self.subtract_numeric_registers(digit2, digit3), // 8xy7
-            (U4::B1000, _, _, U4::B1110) => self.left_shift_register(digit2), // 8xyE
+            (U4::B1000, _, _, U4::B1110) => self.left_shift_register(digit2, digit3), // 8xyE
             (U4::B1001, _, _, U4::B0000) => self.skip_if_registers_not_equal(digit2, digit3), // 9xy0
             (U4::B1010, _, _, _) => self.copy_into_i_register(Self::concat_digits(digit2, digit3, digit4)), // Annn
-            (U4::B1011, _, _, _) => self.offset_register_jump(Self::concat_digits(digit2, digit3, digit4)), // Bnnn
+            (U4::B1011, _, _, _) => self.offset_register_jump(digit2, Self::concat_digits(digit2, digit3, digit4)), // Bnnn
             (U4::B1100, _, _, _) => self.generate_random_value(digit2, byte2), // Cxkk
-            (U4::B1101, _, _, _) => self.draw(digit2, digit3, digit4), // Dxyn
+            (U4::B1101, _, _, _) => self.draw(digit2, digit3, digit4)?, // Dxyn
             (U4::B1110, _, U4::B1001, U4::B1110) => self.skip_if_key_pressed(digit2), // Ex9E
             (U4::B1110, _, U4::B1010, U4::B0001) => self.skip_if_key_not_pressed(digit2), // ExA1
+            (U4::B1111, U4::B0000, U4::B0000, U4::B0000) => self.long_copy_into_i_register()?, // F000 nnnn
+            (U4::B1111, _, U4::B0000, U4::B0001) => self.select_planes(digit2), // Fn01
+            (U4::B1111, U4::B0000, U4::B0000, U4::B0010) => self.load_audio_pattern()?, // F002
             (U4::B1111, _, U4::B0000, U4::B0111) => self.copy_dt_into_register(digit2), // Fx07
             (U4::B1111, _, U4::B0000, U4::B1010) => self.wait_for_key_press(digit2), // Fx0A
             (U4::B1111, _, U4::B0001, U4::B0101) => self.set_delay_timer(digit2), // Fx15
             (U4::B1111, _, U4::B0001, U4::B1000) => self.set_sound_timer(digit2), // Fx18
             (U4::B1111, _, U4::B0001, U4::B1110) => self.add_to_i_register(digit2), // Fx1E
             (U4::B1111, _, U4::B0010, U4::B1001) => self.get_digit_sprite_location(digit2), // Fx29
-            (U4::B1111, _, U4::B0011, U4::B0011) => self.bcd_representation(digit2), // Fx33
-            (U4::B1111, _, U4::B0101, U4::B0101) => self.copy_registers_to_memory(digit2), // Fx55
-            (U4::B1111, _, U4::B0110, U4::B0101) => self.copy_memory_into_registers(digit2), // Fx65
-            _ => println!("Error: illegal instruction 0x{:x}{:x}!", byte1, byte2),
+            (U4::B1111, _, U4::B0011, U4::B0000) => self.get_big_digit_sprite_location(digit2), // Fx30
+            (U4::B1111, _, U4::B0011, U4::B0011) => self.bcd_representation(digit2)?, // Fx33
+            (U4::B1111, _, U4::B0011, U4::B1010) => self.set_pitch(digit2), // Fx3A
+            (U4::B1111, _, U4::B0101, U4::B0101) => self.copy_registers_to_memory(digit2)?, // Fx55
+            (U4::B1111, _, U4::B0110, U4::B0101) => self.copy_memory_into_registers(digit2)?, // Fx65
+            (U4::B1111, _, U4::B0111, U4::B0101) => self.save_rpl_flags(digit2), // Fx75
+            (U4::B1111, _, U4::B1000, U4::B0101) => self.load_rpl_flags(digit2), // Fx85
+            _ => return Err(CpuError::IllegalOpcode { pc: self.instruction_pc, opcode: self.opcode }),
         };
+
+        Ok(())
     }
 
     /// Tick the sound timer and delay timer, decreasing them by 1.
@@ -162,41 +411,119 @@ impl CPU{
 
     // Documentation based on http://devernay.free.fr/hacks/chip8/C8TECH10.HTM
 
-    /// Clears the display (opcode `00E0`).
+    /// Shifts the selected planes of the display by (dx, dy) pixels. Pixels scrolled in from the edge are blank.
+    fn scroll(&mut self, dx: isize, dy: isize){
+        let (width, height) = (self.screen_width() as isize, self.screen_height() as isize);
+        let previous = self.screen;
+        for y in 0..height {
+            for x in 0..width {
+                let (source_x, source_y) = (x - dx, y - dy);
+                let source = if (0..width).contains(&source_x) && (0..height).contains(&source_y) {
+                    previous[source_y as usize][source_x as usize]
+                } else { 0 };
+                let pixel = &mut self.screen[y as usize][x as usize];
+                *pixel = (*pixel & !self.planes) | (source & self.planes);
+            }
+        }
+    }
+
+    /// Scrolls the display down by n pixels (opcode `00Cn`, SCHIP).
+    fn scroll_down(&mut self, n: U4){
+        self.scroll(0, n as isize);
+    }
+
+    /// Scrolls the display up by n pixels (opcode `00Dn`, XO-CHIP).
+    fn scroll_up(&mut self, n: U4){
+        self.scroll(0, -(n as isize));
+    }
+
+    /// Clears the selected planes of the display (opcode `00E0`).
     fn clear(&mut self){
-        self.screen = [[false; 64]; 32];
+        for row in self.screen.iter_mut() {
+            for pixel in row.iter_mut() { *pixel &= !self.planes; }
+        }
     }
 
     /// Return from a subroutine (opcode `00EE`). 
     /// The program counter is set to the value at the top of the stack, and the stack pointer is decremented.
-    fn ret(&mut self) {
-        self.pc = self.pop();
+    fn ret(&mut self) -> Result<(), CpuError> {
+        self.pc = self.pop()?;
+        Ok(())
     }
     
+    /// Scrolls the display right by 4 pixels (opcode `00FB`, SCHIP).
+    fn scroll_right(&mut self){
+        self.scroll(4, 0);
+    }
+
+    /// Scrolls the display left by 4 pixels (opcode `00FC`, SCHIP).
+    fn scroll_left(&mut self){
+        self.scroll(-4, 0);
+    }
+
+    /// Exits the interpreter (opcode `00FD`, SCHIP). No further instructions are executed.
+    fn exit(&mut self){
+        self.exited = true;
+    }
+
+    /// Switches between low-resolution (64x32, opcode `00FE`) and high-resolution (128x64, opcode `00FF`) modes.
+    /// All planes of the display are cleared whenever the resolution changes.
+    fn set_resolution(&mut self, hires: bool){
+        self.hires = hires;
+        self.screen = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+    }
+
     /// Jump to `addr` (opcode `2nnn`).
     fn jump(&mut self, addr: U12) {
         self.pc = addr.into();
     }
 
     /// Calls a subroutine starting at `addr` (opcode `3nnn`).
-    fn call(&mut self, addr: U12) {
-        self.push(self.pc);
+    fn call(&mut self, addr: U12) -> Result<(), CpuError> {
+        self.push(self.pc)?;
         self.pc = addr.into();
+        Ok(())
     }
 
-    /// Skips the next instruction if Vx = kk (opcode `3xkk`), by incrementing the program counter by 2.
+    /// Skips the next instruction if Vx = kk (opcode `3xkk`), by advancing the program counter past it.
     fn skip_if_equal(&mut self, x: U4, kk: u8) {
-        if self.registers[x as usize] == kk { self.pc += 2; }
+        if self.registers[x as usize] == kk { self.skip_next_instruction(); }
     }
 
-    /// Skips the next instruction if Vx != kk (opcode `4xkk`), by incrementing the program counter by 2.
+    /// Skips the next instruction if Vx != kk (opcode `4xkk`), by advancing the program counter past it.
     fn skip_if_not_equal(&mut self, x: U4, kk: u8) {
-        if self.registers[x as usize] != kk { self.pc += 2; }
+        if self.registers[x as usize] != kk { self.skip_next_instruction(); }
     }
 
-    /// Skips the next instruction if Vx = Vy (opcode `5xy0`), by incrementing the program counter by 2.
+    /// Skips the next instruction if Vx = Vy (opcode `5xy0`), by advancing the program counter past it.
     fn skip_if_registers_equal(&mut self, x: U4, y: U4) {
-        if self.registers[x as usize] == self.registers[y as usize] { self.pc += 2; }
+        if self.registers[x as usize] == self.registers[y as usize] { self.skip_next_instruction(); }
+    }
+
+    /// Stores registers Vx through Vy in memory starting at location I, without modifying I (opcode `5xy2`, XO-CHIP).
+    /// If x > y, the registers are stored in reverse order.
+    fn save_register_range(&mut self, x: U4, y: U4) -> Result<(), CpuError> {
+        self.check_memory(self.i as usize, Self::register_range(x, y).count())?;
+        for (offset, register) in Self::register_range(x, y).enumerate() {
+            self.memory[self.i as usize + offset] = self.registers[register];
+        }
+        Ok(())
+    }
+
+    /// Reads registers Vx through Vy from memory starting at location I, without modifying I (opcode `5xy3`, XO-CHIP).
+    /// If x > y, the registers are loaded in reverse order.
+    fn load_register_range(&mut self, x: U4, y: U4) -> Result<(), CpuError> {
+        self.check_memory(self.i as usize, Self::register_range(x, y).count())?;
+        for (offset, register) in Self::register_range(x, y).enumerate() {
+            self.registers[register] = self.memory[self.i as usize + offset];
+        }
+        Ok(())
+    }
+
+    /// Lists the register indices from x to y inclusive, counting downwards if x > y.
+    fn register_range(x: U4, y: U4) -> Box<dyn Iterator<Item = usize>> {
+        let (x, y) = (x as usize, y as usize);
+        if x <= y { Box::new(x..=y) } else { Box::new((y..=x).rev()) }
     }
 
     /// Sets Vx = kk (opcode `6xkk`).
@@ -216,18 +543,24 @@ impl CPU{
     }
 
     /// Sets Vx = Vx | Vy (opcode `8xy1`).
+    /// With the `logic_resets_vf` quirk, VF is also reset to 0.
     fn or_registers(&mut self, x: U4, y: U4){
-        self.registers[x as usize] = self.registers[x as usize] | self.registers[y as usize];
+        self.registers[x as usize] |= self.registers[y as usize];
+        if self.quirks.logic_resets_vf { self.registers[0xF] = 0; }
     }
 
     /// Sets Vx = Vx & Vy (opcode `8xy2`).
+    /// With the `logic_resets_vf` quirk, VF is also reset to 0.
     fn and_registers(&mut self, x: U4, y: U4){
-        self.registers[x as usize] = self.registers[x as usize] & self.registers[y as usize];
+        self.registers[x as usize] &= self.registers[y as usize];
+        if self.quirks.logic_resets_vf { self.registers[0xF] = 0; }
     }
 
     /// Sets Vx = Vx ^ Vy (opcode `8xy3`).
+    /// With the `logic_resets_vf` quirk, VF is also reset to 0.
     fn xor_registers(&mut self, x: U4, y: U4){
-        self.registers[x as usize] = self.registers[x as usize] ^ self.registers[y as usize];
+        self.registers[x as usize] ^= self.registers[y as usize];
+        if self.quirks.logic_resets_vf { self.registers[0xF] = 0; }
     }
 
     /// Sets Vx = Vx + Vy (opcode `8xy4`).
@@ -251,12 +584,14 @@ impl CPU{
         self.registers[0xF] = !borrow as u8;
     }
 
-    /// Sets Vx = Vx SHR 1 (opcode `8xy6`), in effect dividing by 2.
+    /// Sets Vx = Vy SHR 1 (opcode `8xy6`), in effect dividing by 2.
+    /// With the `shift_ignores_vy` quirk, Vx is shifted in place instead.
     /// VF is set equal to the bit that was shifted out.
-    fn right_shift_register(&mut self, x: U4){
-        let shifted_bit = self.registers[x as usize] & 1;
-        self.registers[x as usize] = self.registers[x as usize] >> 1;
-        self.registers[0xF] = shifted_bit;
+    fn right_shift_register(&mut self, x: U4, y: U4){
+        let source = if self.quirks.shift_ignores_vy { self.registers[x as usize] } else { self.registers[y as usize] };
+        self.registers[x as usize] = source >> 1;
+        // VF always updated after register written to, in event Vx = VF.
+        self.registers[0xF] = source & 1;
     }
 
     /// Sets Vx = Vy - Vx (opcode `8xy7`) and VF = NOT borrow.
@@ -270,17 +605,26 @@ impl CPU{
         self.registers[0xF] = !borrow as u8;
     }
 
-    /// Sets Vx = Vx SHL 1 (opcode `8xyE`), in effect multiplying by 2.
+    /// Sets Vx = Vy SHL 1 (opcode `8xyE`), in effect multiplying by 2.
+    /// With the `shift_ignores_vy` quirk, Vx is shifted in place instead.
     /// VF is set equal to the bit that was shifted out.
-    fn left_shift_register(&mut self, x: U4){
-        let msb = self.registers[x as usize] & 0x80;
-        self.registers[x as usize] = self.registers[x as usize] << 1;
-        self.registers[0xF] = if msb == 0x80 { 1 } else { 0 };
+    fn left_shift_register(&mut self, x: U4, y: U4){
+        let source = if self.quirks.shift_ignores_vy { self.registers[x as usize] } else { self.registers[y as usize] };
+        self.registers[x as usize] = source << 1;
+        // VF always updated after register written to, in event Vx = VF.
+        self.registers[0xF] = source >> 7;
     }
 
-    /// Skips the next instruction if Vx != Vy (opcode `9xy0`), by incrementing the program counter by 2.
+    /// Skips the next instruction if Vx != Vy (opcode `9xy0`), by advancing the program counter past it.
     fn skip_if_registers_not_equal(&mut self, x: U4, y: U4) {
-        if self.registers[x as usize] != self.registers[y as usize] { self.pc += 2; }
+        if self.registers[x as usize] != self.registers[y as usize] { self.skip_next_instruction(); }
+    }
+
+    /// Advances the program counter past the next instruction.
+    /// XO-CHIP's `F000 nnnn` is four bytes long, so is skipped as a whole.
+    fn skip_next_instruction(&mut self){
+        let long = self.memory.get(self.pc as usize) == Some(&0xF0) && self.memory.get(self.pc as usize + 1) == Some(&0x00);
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
     }
 
     /// Sets I = nnn (opcode `Annn`).
@@ -289,54 +633,76 @@ impl CPU{
     }
 
     /// Jump to location nnn + V0 (opcode `Bnnn`), by changing the program counter.
-    fn offset_register_jump(&mut self, nnn: U12){
-        self.pc = u16::from(nnn) + self.registers[0] as u16;
+    /// With the `jump_uses_vx` quirk, the offset is taken from Vx instead (opcode `Bxnn`), where x is the top digit of nnn.
+    fn offset_register_jump(&mut self, x: U4, nnn: U12){
+        let offset = if self.quirks.jump_uses_vx { self.registers[x as usize] } else { self.registers[0] };
+        self.pc = u16::from(nnn) + offset as u16;
     }
 
     /// Set Vx = rand & kk (opcode `Cxkk`), where rand is randomly generated (between 0 and 255).
     fn generate_random_value(&mut self, x: U4, kk: u8){
-        let mut rng = rand::thread_rng();
-        let random_value : u8 = rng.gen();
+        let random_value = self.rng.next_byte();
         self.registers[x as usize] = kk & random_value;
     }
 
     /// Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision (opcode `Dxyn`).
-    fn draw(&mut self, x: U4, y: U4, n: U4){
+    /// If n = 0, a 16x16 sprite made of 32 bytes (two per row) is drawn instead (opcode `Dxy0`, SCHIP).
+    /// When both planes are selected (XO-CHIP), the sprite data for plane 2 immediately follows that for plane 1.
+    /// The starting position always wraps around the screen; with the `clip_sprites` quirk, pixels beyond the edge are discarded instead of wrapping.
+    fn draw(&mut self, x: U4, y: U4, n: U4) -> Result<(), CpuError> {
+        let (width, height) = (self.screen_width(), self.screen_height());
+        let starting_x = self.registers[x as usize] as usize % width;
+        let starting_y = self.registers[y as usize] as usize % height;
+
+        let (rows, bytes_per_row) = if n == U4::B0000 { (16, 2) } else { (n as usize, 1) };
+        let row_width = 8 * bytes_per_row;
+        self.check_memory(self.i as usize, rows * bytes_per_row * self.planes.count_ones() as usize)?;
+
         self.registers[0xF] = 0;
-        
-        let starting_x = self.registers[x as usize];
-        let starting_y = self.registers[y as usize];
-
-        // Each byte is one row of the sprite
-        for line in 0..(n as u8){
-            // Get byte, iterate over bits from left-to-right
-            let next_byte : u8 = self.memory[self.i as usize + line as usize];
-            for x_iter in 0..8{
-                // Get next bit via bit shift and mask, convert to bool
-                // e.g., second column of sprite via 1 left shift and mask with 10000000
-                let pixel: bool = ((next_byte << x_iter) & 0x80) != 0;
-
-                // Wrap-around if past edge of screen
-                let x_pos: usize = ((starting_x + x_iter) % 64) as usize;
-                let y_pos: usize = ((starting_y + line) % 32) as usize;
-
-                // Check for collision
-                if pixel && self.screen[y_pos][x_pos] { self.registers[0xF] = 1; }
-
-                // Update screen
-                self.screen[y_pos][x_pos] = self.screen[y_pos][x_pos] ^ pixel;
+
+        let mut sprite_address = self.i as usize;
+        for plane in [1u8, 2u8]{
+            if self.planes & plane == 0 { continue; }
+
+            // Each byte (or pair of bytes, for 16x16 sprites) is one row of the sprite
+            for line in 0..rows{
+                // Get row, iterate over bits from left-to-right
+                let mut next_row: u16 = 0;
+                for byte in 0..bytes_per_row{
+                    next_row = (next_row << 8) | self.memory[sprite_address + line * bytes_per_row + byte] as u16;
+                }
+                for x_iter in 0..row_width{
+                    // Get next bit via bit shift and mask
+                    // e.g., second column of an 8-pixel sprite via 6 right shifts and mask with 00000001
+                    if (next_row >> (row_width - 1 - x_iter)) & 1 == 0 { continue; }
+
+                    // Clip or wrap-around if past edge of screen
+                    if self.quirks.clip_sprites && (starting_x + x_iter >= width || starting_y + line >= height) { continue; }
+                    let x_pos: usize = (starting_x + x_iter) % width;
+                    let y_pos: usize = (starting_y + line) % height;
+
+                    // Check for collision
+                    if self.screen[y_pos][x_pos] & plane != 0 { self.registers[0xF] = 1; }
+
+                    // Update screen
+                    self.screen[y_pos][x_pos] ^= plane;
+                }
             }
+
+            sprite_address += rows * bytes_per_row;
         }
+
+        Ok(())
     }
 
-    /// Skips the next instruction if the key with the value of Vx is pressed (opcode `Ex9E`), by increasing the program counter by 2.
+    /// Skips the next instruction if the key with the value of Vx is pressed (opcode `Ex9E`), by advancing the program counter past it.
     fn skip_if_key_pressed(&mut self, x: U4){
-        if self.keyboard[self.registers[x as usize] as usize] { self.pc += 2; }
+        if self.keyboard[self.registers[x as usize] as usize & 0xF] { self.skip_next_instruction(); }
     }
 
-    /// Skips the next instruction if the key with the value of Vx is pressed (opcode `Ex9E`), by increasing the program counter by 2.
+    /// Skips the next instruction if the key with the value of Vx is not pressed (opcode `ExA1`), by advancing the program counter past it.
     fn skip_if_key_not_pressed(&mut self, x: U4){
-        if !self.keyboard[self.registers[x as usize] as usize] { self.pc += 2; }
+        if !self.keyboard[self.registers[x as usize] as usize & 0xF] { self.skip_next_instruction(); }
     }
 
     /// Set Vx = delay timer value (opcode Fx07).
@@ -345,22 +711,50 @@ impl CPU{
     }
 
     /// Waits for a key press, then store the value of the key in Vx (opcode `Fx0A`). All execution stops until a key is pressed.
-    /// Keys are polled in numerical order (0 through F). This is thread-safe; if no key is pressed, the instruction is repeated.
+    /// With the `key_release` quirk, the key must also be released. Keys held when the wait begins are ignored until
+    /// released, and if several keys are pressed at once, the lowest (0 through F) is taken.
     fn wait_for_key_press(&mut self, x: U4){
-        let mut pressed: bool = false;
-        for key in 0..0xF{
-            if self.keyboard[key as usize] { 
+        let keys = self.key_mask();
+        let wait = self.key_wait.get_or_insert(KeyWait { held: keys, pressed: None });
+        wait.held &= keys;
+        if wait.pressed.is_none() {
+            let new_keys = keys & !wait.held;
+            if new_keys != 0 { wait.pressed = Some(new_keys.trailing_zeros() as u8); }
+        }
+
+        match wait.pressed {
+            Some(key) if !self.quirks.key_release || keys & (1 << key) == 0 => {
                 self.registers[x as usize] = key;
-                pressed = true; 
-                break; 
-            } 
+                self.key_wait = None;
+            },
+            // We can't hold an infinite loop here, else the graphics thread (e.g., SDL2) will hang
+            //  and most OSes will think the interpreter is not responding. Instead, we'll reduce
+            //  the program counter and "repeat" the instruction, in effect causing the program
+            //  to not move forward to the next instruction until a key is pressed.
+            _ => self.pc = self.pc.wrapping_sub(2),
         }
+    }
+
+    /// Sets I = nnnn, where nnnn is the 16-bit word following the instruction (opcode `F000 nnnn`, XO-CHIP).
+    fn long_copy_into_i_register(&mut self) -> Result<(), CpuError> {
+        self.check_memory(self.pc as usize, 2)?;
+        self.i = (self.memory[self.pc as usize] as u16) << 8 | self.memory[self.pc as usize + 1] as u16;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    /// Selects the drawing planes given by the bitmask n (opcode `Fn01`, XO-CHIP).
+    fn select_planes(&mut self, n: U4){
+        self.planes = n as u8 & 0b11;
+    }
 
-        // We can't hold an infinite loop here, else the graphics thread (e.g., SDL2) will hang
-        //  and most OSes will think the interpreter is not responding. Instead, we'll reduce
-        //  the program counter and "repeat" the instruction, in effect causing the program
-        //  to not move forward to the next instruction until a key is pressed.
-        if !pressed { self.pc -= 2; }
+    /// Loads the 16 bytes starting at location I into the audio pattern buffer (opcode `F002`, XO-CHIP).
+    fn load_audio_pattern(&mut self) -> Result<(), CpuError> {
+        self.check_memory(self.i as usize, 16)?;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(&self.memory[self.i as usize..self.i as usize + 16]);
+        self.audio_pattern = Some(pattern);
+        Ok(())
     }
 
     /// Set delay timer value = Vx (opcode Fx15).
@@ -375,7 +769,7 @@ impl CPU{
 
     /// Set I = I + Vx (opcode Fx1E).
     fn add_to_i_register(&mut self, x: U4){
-        self.i = self.i + self.registers[x as usize] as u16;
+        self.i = self.i.wrapping_add(self.registers[x as usize] as u16);
     }
 
     /// Gets the address of the hexadecimal sprite corresponding to the value of Vx, and copies this into I (opcode `Fx29`).
@@ -387,10 +781,20 @@ impl CPU{
         self.i = 5 * target as u16;
     }
 
+    /// Gets the address of the large (8x10) hexadecimal sprite corresponding to the value of Vx, and copies this into I (opcode `Fx30`, SCHIP).
+    fn get_big_digit_sprite_location(&mut self, x: U4){
+        let target = self.registers[x as usize] & 0xF;
+
+        // Each large character sprite is 10 bytes starting at BIG_FONT_ADDRESS
+        self.i = (BIG_FONT_ADDRESS + 10 * target as usize) as u16;
+    }
+
     /// Stores the BCD representation of Vx in memory locations I, I+1, and I+2 (opcode `Fx33`).
     /// The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I, 
     ///   the tens digit at location I+1, and the ones digit at location I+2.
-    fn bcd_representation(&mut self, x: U4){
+    fn bcd_representation(&mut self, x: U4) -> Result<(), CpuError> {
+        self.check_memory(self.i as usize, 3)?;
+
         let value: f32 = self.registers[x as usize] as f32;
 
         // Get 100s, 10s, and 1s digits (in decimal)
@@ -401,25 +805,60 @@ impl CPU{
         self.memory[self.i as usize] = hundreds;
         self.memory[self.i as usize + 1] = tens;
         self.memory[self.i as usize + 2] = ones;
+        Ok(())
     }
 
     /// Stores registers V0 through Vx in memory starting at location I (opcode `Fx55`).
-    fn copy_registers_to_memory(&mut self, x: U4){
+    /// I is then advanced according to the `index_increment` quirk.
+    fn copy_registers_to_memory(&mut self, x: U4) -> Result<(), CpuError> {
+        self.check_memory(self.i as usize, x as usize + 1)?;
         for count in 0..(x as usize)+1{
             self.memory[self.i as usize + count] = self.registers[count];
         }
+        self.increment_i_after_copy(x);
+        Ok(())
     }
 
     /// Reads registers V0 through Vx from memory starting at location I (opcode `Fx65`).
-    fn copy_memory_into_registers(&mut self, x: U4){
+    /// I is then advanced according to the `index_increment` quirk.
+    fn copy_memory_into_registers(&mut self, x: U4) -> Result<(), CpuError> {
+        self.check_memory(self.i as usize, x as usize + 1)?;
         for count in 0..(x as usize)+1{
             self.registers[count] = self.memory[self.i as usize + count];
         }
+        self.increment_i_after_copy(x);
+        Ok(())
+    }
+
+    /// Advances I after `Fx55`/`Fx65`, depending on the platform being emulated.
+    fn increment_i_after_copy(&mut self, x: U4){
+        match self.quirks.index_increment {
+            IndexIncrement::None => { },
+            IndexIncrement::X => self.i = self.i.wrapping_add(x as u16),
+            IndexIncrement::XPlusOne => self.i = self.i.wrapping_add(x as u16 + 1),
+        }
+    }
+
+    /// Sets the audio playback pitch = Vx (opcode `Fx3A`, XO-CHIP).
+    fn set_pitch(&mut self, x: U4){
+        self.pitch = self.registers[x as usize];
+    }
+
+    /// Stores registers V0 through Vx in the RPL user flags (opcode `Fx75`, SCHIP).
+    fn save_rpl_flags(&mut self, x: U4){
+        let count = x as usize + 1;
+        self.rpl[..count].copy_from_slice(&self.registers[..count]);
+    }
+
+    /// Reads registers V0 through Vx from the RPL user flags (opcode `Fx85`, SCHIP).
+    fn load_rpl_flags(&mut self, x: U4){
+        let count = x as usize + 1;
+        self.registers[..count].copy_from_slice(&self.rpl[..count]);
     }
 
     /// Saves the current CPU state.
     pub fn save_state(&mut self){
-        self.saved_state = Box::new(Some(self.clone()));
+        *self.saved_state = Some(self.clone());
     }
 
     /// Loads the saved CPU state. Does nothing if no save state is present.
@@ -429,20 +868,25 @@ impl CPU{
 
         // Update this instance of CPU is saved state persent
         // Saved state is left unchanged, so it can be reloaded again
-        match state{
-            Some(saved_cpu) => { 
-                self.memory = saved_cpu.memory;
-                self.registers = saved_cpu.registers;
-                self.i = saved_cpu.i;
-                self.dt = saved_cpu.dt;
-                self.st = saved_cpu.st;
-                self.pc = saved_cpu.pc;
-                self.sp = saved_cpu.sp;
-                self.stack = saved_cpu.stack;
-                self.screen = saved_cpu.screen;
-                self.keyboard = saved_cpu.keyboard;
-            },
-            None => return,
+        if let Some(saved_cpu) = state {
+            self.memory = saved_cpu.memory;
+            self.registers = saved_cpu.registers;
+            self.i = saved_cpu.i;
+            self.dt = saved_cpu.dt;
+            self.st = saved_cpu.st;
+            self.pc = saved_cpu.pc;
+            self.sp = saved_cpu.sp;
+            self.stack = saved_cpu.stack;
+            self.screen = saved_cpu.screen;
+            self.hires = saved_cpu.hires;
+            self.planes = saved_cpu.planes;
+            self.audio_pattern = saved_cpu.audio_pattern;
+            self.pitch = saved_cpu.pitch;
+            self.exited = saved_cpu.exited;
+            self.rpl = saved_cpu.rpl;
+            self.keyboard = saved_cpu.keyboard;
+            self.key_wait = saved_cpu.key_wait;
+            self.rng = saved_cpu.rng;
         }
     }
 }
\ No newline at end of file
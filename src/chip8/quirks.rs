@@ -0,0 +1,175 @@
+use super::MEMORY_SIZE;
+
+/// Behaviour of the load/store instructions (`Fx55` and `Fx65`) with respect to the I register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left untouched (SCHIP 1.1).
+    None,
+    /// I is incremented by x (CHIP-48).
+    X,
+    /// I is incremented by x + 1, as on the original COSMAC VIP (and XO-CHIP).
+    XPlusOne,
+}
+
+/// The set of ambiguous instruction behaviours ("quirks") honoured by the CPU.
+/// Different CHIP-8 implementations disagree on these, so ROMs written for one platform may misbehave on another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` shift Vx in place, ignoring Vy. If false, Vx = Vy shifted.
+    pub shift_ignores_vy: bool,
+    /// How `Fx55`/`Fx65` modify I after copying.
+    pub index_increment: IndexIncrement,
+    /// `Bnnn` is treated as `Bxnn`, jumping to xnn + Vx rather than nnn + V0.
+    pub jump_uses_vx: bool,
+    /// `8xy1`, `8xy2` and `8xy3` reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// Sprites drawn with `Dxyn` are clipped at the screen edge rather than wrapped around.
+    pub clip_sprites: bool,
+    /// `Dxyn` waits for the next frame, as on the VIP, where drawing waits for the display's vertical interrupt.
+    /// [`CPU::step`](super::CPU::step) returns [`StepOutcome::DisplayWait`](super::StepOutcome::DisplayWait) after a
+    /// draw, and the frontend runs no more instructions until the next frame.
+    pub display_wait: bool,
+    /// `Fx0A` waits for a key to be pressed and then released, as on the VIP. If false, it returns as soon as the key
+    /// is pressed. Either way, keys already held when the wait begins only count once released and pressed again.
+    pub key_release: bool,
+    /// Memory is 64KB, as on XO-CHIP. If false, it is 4KB, as on the VIP, CHIP-48 and SCHIP, and ROMs or memory
+    /// accesses beyond it fail.
+    pub large_memory: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const VIP: Quirks = Quirks {
+        shift_ignores_vy: false,
+        index_increment: IndexIncrement::XPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        clip_sprites: true,
+        display_wait: false,
+        key_release: true,
+        large_memory: false,
+    };
+
+    /// CHIP-48, for the HP-48 calculators.
+    pub const CHIP48: Quirks = Quirks {
+        shift_ignores_vy: true,
+        index_increment: IndexIncrement::X,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        key_release: false,
+        large_memory: false,
+    };
+
+    /// SUPER-CHIP 1.1.
+    pub const SCHIP: Quirks = Quirks {
+        shift_ignores_vy: true,
+        index_increment: IndexIncrement::None,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        key_release: false,
+        large_memory: false,
+    };
+
+    /// XO-CHIP, as implemented by Octo.
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_ignores_vy: false,
+        index_increment: IndexIncrement::XPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false,
+        display_wait: false,
+        key_release: true,
+        large_memory: true,
+    };
+
+    /// Looks up a preset by name (`vip`, `chip48`, `schip` or `xochip`), ignoring case and dashes.
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_lowercase().replace(['-', '_', '.', ' '], "").as_str() {
+            "vip" | "chip8" | "cosmacvip" => Some(Quirks::VIP),
+            "chip48" => Some(Quirks::CHIP48),
+            "schip" | "schip11" | "superchip" => Some(Quirks::SCHIP),
+            "xochip" => Some(Quirks::XO_CHIP),
+            _ => None,
+        }
+    }
+
+    /// The name of the preset these quirks match, if any.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        [(Quirks::VIP, "vip"), (Quirks::CHIP48, "chip48"), (Quirks::SCHIP, "schip"), (Quirks::XO_CHIP, "xochip")]
+            .into_iter().find(|(preset, _)| preset == self).map(|(_, name)| name)
+    }
+
+    /// Switches an on/off quirk by its field name, e.g., `display_wait`. Fails if there is no such quirk.
+    pub fn set(&mut self, name: &str, on: bool) -> Result<(), String> {
+        let quirk = match name {
+            "shift_ignores_vy" => &mut self.shift_ignores_vy,
+            "jump_uses_vx" => &mut self.jump_uses_vx,
+            "logic_resets_vf" => &mut self.logic_resets_vf,
+            "clip_sprites" => &mut self.clip_sprites,
+            "display_wait" => &mut self.display_wait,
+            "key_release" => &mut self.key_release,
+            "large_memory" => &mut self.large_memory,
+            _ => return Err(format!("Unknown quirk '{}'! Expected one of: {}.", name, Self::NAMES.join(", "))),
+        };
+        *quirk = on;
+        Ok(())
+    }
+
+    /// The names of the on/off quirks, as accepted by [`Quirks::set`].
+    pub const NAMES: [&'static str; 7] = ["shift_ignores_vy", "jump_uses_vx", "logic_resets_vf", "clip_sprites", "display_wait", "key_release",
+        "large_memory"];
+
+    /// The size of memory, in bytes: 64KB with `large_memory`, otherwise 4KB.
+    pub fn memory_size(&self) -> usize {
+        if self.large_memory { MEMORY_SIZE } else { 0x1000 }
+    }
+
+    /// Packs the quirks into a bitfield, for storing alongside recordings and save states.
+    /// Bit 0 is `shift_ignores_vy`, bits 1-2 are `index_increment`, bit 3 is `jump_uses_vx`, bit 4 is `logic_resets_vf`,
+    /// bit 5 is `clip_sprites`, bit 6 is `display_wait`, bit 7 is `key_release` and bit 8 is `large_memory`.
+    pub fn to_bits(&self) -> u32 {
+        let index_increment = match self.index_increment {
+            IndexIncrement::None => 0,
+            IndexIncrement::X => 1,
+            IndexIncrement::XPlusOne => 2,
+        };
+        self.shift_ignores_vy as u32
+            | index_increment << 1
+            | (self.jump_uses_vx as u32) << 3
+            | (self.logic_resets_vf as u32) << 4
+            | (self.clip_sprites as u32) << 5
+            | (self.display_wait as u32) << 6
+            | (self.key_release as u32) << 7
+            | (self.large_memory as u32) << 8
+    }
+
+    /// Unpacks quirks packed by [`Quirks::to_bits`]. Returns None if any unknown bits are set.
+    pub fn from_bits(bits: u32) -> Option<Quirks> {
+        let index_increment = match (bits >> 1) & 0b11 {
+            0 => IndexIncrement::None,
+            1 => IndexIncrement::X,
+            2 => IndexIncrement::XPlusOne,
+            _ => return None,
+        };
+        if bits >> 9 != 0 { return None; }
+        Some(Quirks {
+            shift_ignores_vy: bits & 1 != 0,
+            index_increment,
+            jump_uses_vx: bits & (1 << 3) != 0,
+            logic_resets_vf: bits & (1 << 4) != 0,
+            clip_sprites: bits & (1 << 5) != 0,
+            display_wait: bits & (1 << 6) != 0,
+            key_release: bits & (1 << 7) != 0,
+            large_memory: bits & (1 << 8) != 0,
+        })
+    }
+}
+
+impl Default for Quirks {
+    /// Defaults to the behaviour of the original COSMAC VIP.
+    fn default() -> Self { Quirks::VIP }
+}
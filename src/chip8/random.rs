@@ -0,0 +1,128 @@
+use super::SnapshotError;
+
+/// Size of the random number generator's state in a snapshot, in bytes: a tag followed by the state.
+pub(crate) const RANDOM_STATE_SIZE: usize = 9;
+
+/// Size of the COSMAC VIP's CHIP-8 interpreter, which occupies 0x000-0x1FF of the VIP's memory.
+pub const VIP_INTERPRETER_SIZE: usize = 0x200;
+
+/// The kinds of random number source, for selecting one by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomKind {
+    /// A seeded pseudo-random number generator (the default).
+    Seeded,
+    /// A fixed sequence of values, for tests.
+    Scripted,
+    /// The COSMAC VIP interpreter's `Cxkk` routine.
+    Vip,
+}
+
+impl RandomKind {
+    /// Looks up a kind by name (`seeded` or `vip`). Scripted sources are only available through the library.
+    pub fn from_name(name: &str) -> Option<RandomKind> {
+        match name.to_lowercase().as_str() {
+            "seeded" | "prng" => Some(RandomKind::Seeded),
+            "vip" | "cosmacvip" => Some(RandomKind::Vip),
+            _ => None,
+        }
+    }
+}
+
+/// The source of the values returned by `Cxkk`, owned by the CPU.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RandomSource {
+    /// SplitMix64, a fast pseudo-random number generator whose whole state is one 64-bit word.
+    Seeded { state: u64 },
+    /// Returns `values` in order, starting again from the beginning once they run out (or 0 if there are none).
+    Scripted { values: Vec<u8>, position: usize },
+    /// Runs the COSMAC VIP interpreter's `Cxkk` routine:
+    ///
+    /// ```text
+    /// INC R9; GLO R9; PLO RE; GHI R3; PHI RE    ; RE = 0x0100 + low byte of R9, in the interpreter's code
+    /// GHI R9; SEX RE; ADD; STR R6               ; Vx = M(RE) + high byte of R9 (the previous value)
+    /// SHRC; SEX R6; ADD; PHI R9; STR R6         ; Vx += that sum rotated right through the carry; R9.1 = Vx
+    /// LDA R5; AND; STR R6; SEP R4               ; Vx &= kk
+    /// ```
+    ///
+    /// `r9` is the 1802's R9 register and `page` is the interpreter's second page (0x100-0x1FF), which the routine
+    /// reads as its table of random bytes.
+    Vip { r9: u16, page: Box<[u8; 256]> },
+}
+
+impl RandomSource {
+    /// A seeded pseudo-random number generator.
+    pub fn seeded(seed: u64) -> Self { RandomSource::Seeded { state: seed } }
+
+    /// A fixed sequence of values, repeated once it runs out.
+    pub fn scripted(values: Vec<u8>) -> Self { RandomSource::Scripted { values, position: 0 } }
+
+    /// The COSMAC VIP generator, with R9 starting at the low 16 bits of `seed` (the VIP leaves it as it powers up).
+    /// `interpreter` is an image of the VIP's 512-byte CHIP-8 interpreter, which isn't distributed with this program.
+    pub fn vip(seed: u64, interpreter: &[u8]) -> Result<Self, String> {
+        if interpreter.len() != VIP_INTERPRETER_SIZE {
+            return Err(format!("the COSMAC VIP interpreter is {} bytes, not {}", VIP_INTERPRETER_SIZE, interpreter.len()));
+        }
+        Ok(RandomSource::Vip { r9: seed as u16, page: Box::new(interpreter[0x100..].try_into().unwrap()) })
+    }
+
+    /// The kind of this source.
+    pub fn kind(&self) -> RandomKind {
+        match self {
+            RandomSource::Seeded { .. } => RandomKind::Seeded,
+            RandomSource::Scripted { .. } => RandomKind::Scripted,
+            RandomSource::Vip { .. } => RandomKind::Vip,
+        }
+    }
+
+    /// Returns the next random byte.
+    pub(crate) fn next_byte(&mut self) -> u8 {
+        match self {
+            RandomSource::Seeded { state } => {
+                *state = state.wrapping_add(0x9E3779B97F4A7C15);
+                let mut z = *state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+                ((z ^ (z >> 31)) >> 56) as u8
+            },
+            RandomSource::Scripted { values, position } => {
+                if values.is_empty() { return 0; }
+                let value = values[*position % values.len()];
+                *position = (*position + 1) % values.len();
+                value
+            },
+            RandomSource::Vip { r9, page } => {
+                *r9 = r9.wrapping_add(1);
+                let [pointer, previous] = r9.to_le_bytes();
+                let (sum, carry) = page[pointer as usize].overflowing_add(previous);
+                let value = sum.wrapping_add(sum >> 1 | (carry as u8) << 7);
+                *r9 = u16::from_le_bytes([pointer, value]);
+                value
+            },
+        }
+    }
+
+    /// Serialises the state for a snapshot. A scripted source stores only its position, not its values.
+    pub(crate) fn to_bytes(&self) -> [u8; RANDOM_STATE_SIZE] {
+        let mut bytes = [0; RANDOM_STATE_SIZE];
+        match self {
+            RandomSource::Seeded { state } => bytes[1..].copy_from_slice(&state.to_le_bytes()),
+            RandomSource::Scripted { position, .. } => { bytes[0] = 1; bytes[1..].copy_from_slice(&(*position as u64).to_le_bytes()); },
+            RandomSource::Vip { r9, .. } => { bytes[0] = 2; bytes[1..3].copy_from_slice(&r9.to_le_bytes()); },
+        }
+        bytes
+    }
+
+    /// Returns this source with the state serialised by `to_bytes` restored.
+    /// A scripted position can only be restored into a scripted source, and VIP state into a VIP source, as the values
+    /// and interpreter page themselves aren't serialised.
+    pub(crate) fn restored(&self, bytes: &[u8]) -> Result<RandomSource, SnapshotError> {
+        let word = u64::from_le_bytes(bytes[1..RANDOM_STATE_SIZE].try_into().unwrap());
+        match (bytes[0], self) {
+            (0, _) => Ok(RandomSource::Seeded { state: word }),
+            (1, RandomSource::Scripted { values, .. }) if values.is_empty() || (word as usize) < values.len() =>
+                Ok(RandomSource::Scripted { values: values.clone(), position: word as usize }),
+            (2, RandomSource::Vip { page, .. }) if word >> 16 == 0 => Ok(RandomSource::Vip { r9: word as u16, page: page.clone() }),
+            _ => Err(SnapshotError::InvalidField("random number generator")),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use super::{CPU, MEMORY_SIZE, SCREEN_WIDTH, SCREEN_HEIGHT, SnapshotError};
+use super::random::RANDOM_STATE_SIZE;
+
+/// Size of a snapshot, in bytes. Snapshots are a fixed size, so that they can be compared byte-for-byte.
+pub const SNAPSHOT_SIZE: usize = MEMORY_SIZE // memory
+    + 16 + 2 + 1 + 1 + 2 + 1 + 32 // registers, i, dt, st, pc, sp, stack
+    + SCREEN_WIDTH * SCREEN_HEIGHT // screen
+    + 1 + 1 + 1 + 16 + 1 + 1 // hires, planes, audio pattern (flag and contents), pitch, exited
+    + 16 // RPL user flags
+    + RANDOM_STATE_SIZE; // random number generator
+
+impl CPU{
+    /// Serialises the machine state into a flat block of bytes, which [`CPU::restore`] can load back.
+    /// This covers the same state as [`CPU::save_state`], including the random number generator, so a restored CPU
+    /// continues identically. The RPL user flags are included too, but the keyboard and quirks are not.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(SNAPSHOT_SIZE);
+        data.extend_from_slice(&self.memory);
+        data.extend_from_slice(&self.registers);
+        data.extend_from_slice(&self.i.to_le_bytes());
+        data.push(self.dt);
+        data.push(self.st);
+        data.extend_from_slice(&self.pc.to_le_bytes());
+        data.push(self.sp);
+        for entry in self.stack { data.extend_from_slice(&entry.to_le_bytes()); }
+        for row in &self.screen { data.extend_from_slice(row); }
+        data.push(self.hires as u8);
+        data.push(self.planes);
+        data.push(self.audio_pattern.is_some() as u8);
+        data.extend_from_slice(&self.audio_pattern.unwrap_or([0; 16]));
+        data.push(self.pitch);
+        data.push(self.exited as u8);
+        data.extend_from_slice(&self.rpl);
+        data.extend_from_slice(&self.rng.to_bytes());
+        data
+    }
+
+    /// Loads machine state from a snapshot made by [`CPU::snapshot`].
+    /// The snapshot is validated first, so the CPU is left unchanged if it is rejected.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        if snapshot.len() != SNAPSHOT_SIZE {
+            return Err(SnapshotError::WrongSize { expected: SNAPSHOT_SIZE, found: snapshot.len() });
+        }
+
+        let mut reader = Reader { data: snapshot, position: 0 };
+        let memory = reader.take(MEMORY_SIZE);
+        let registers = reader.take(16);
+        let i = reader.word();
+        let dt = reader.byte();
+        let st = reader.byte();
+        let pc = reader.word();
+        let sp = reader.byte();
+        let mut stack = [0; 16];
+        for entry in stack.iter_mut() { *entry = reader.word(); }
+        let screen = reader.take(SCREEN_WIDTH * SCREEN_HEIGHT);
+        let hires = reader.flag("hires")?;
+        let planes = reader.byte();
+        let has_pattern = reader.flag("audio pattern")?;
+        let pattern = reader.take(16);
+        let pitch = reader.byte();
+        let exited = reader.flag("exited")?;
+        let rpl = reader.take(16);
+        let rng = self.rng.restored(reader.take(RANDOM_STATE_SIZE))?;
+
+        if sp as usize > stack.len() { return Err(SnapshotError::InvalidField("stack pointer")); }
+        if planes > 0b11 { return Err(SnapshotError::InvalidField("planes")); }
+        if screen.iter().any(|pixel| *pixel > 0b11) { return Err(SnapshotError::InvalidField("screen")); }
+
+        self.memory.copy_from_slice(memory);
+        self.registers.copy_from_slice(registers);
+        self.i = i;
+        self.dt = dt;
+        self.st = st;
+        self.pc = pc;
+        self.sp = sp;
+        self.stack = stack;
+        for (row, pixels) in self.screen.iter_mut().zip(screen.chunks(SCREEN_WIDTH)) { row.copy_from_slice(pixels); }
+        self.hires = hires;
+        self.planes = planes;
+        self.audio_pattern = if has_pattern { Some(pattern.try_into().unwrap()) } else { None };
+        self.pitch = pitch;
+        self.exited = exited;
+        self.rpl.copy_from_slice(rpl);
+        self.rng = rng;
+        // Any Fx0A in progress starts waiting afresh
+        self.key_wait = None;
+        Ok(())
+    }
+}
+
+/// Reads fields in order from a snapshot whose size has already been checked.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> &'a [u8] {
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        bytes
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn word(&mut self) -> u16 {
+        u16::from_le_bytes([self.byte(), self.byte()])
+    }
+
+    fn flag(&mut self, name: &'static str) -> Result<bool, SnapshotError> {
+        match self.byte() {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::InvalidField(name)),
+        }
+    }
+}
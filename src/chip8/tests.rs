@@ -0,0 +1,709 @@
+use super::*;
+
+/// Creates a CPU with the given quirks, and `I` pointing at a scratch area of memory.
+fn new_cpu(quirks: Quirks) -> CPU {
+    let mut cpu = CPU::new(quirks);
+    cpu.i = 0x300;
+    cpu
+}
+
+/// Writes `opcode` at the program counter and executes it.
+fn exec(cpu: &mut CPU, opcode: u16) -> Result<StepOutcome, CpuError> {
+    let pc = cpu.pc as usize;
+    cpu.memory[pc..pc + 2].copy_from_slice(&opcode.to_be_bytes());
+    cpu.step()
+}
+
+/// Executes `opcode`, which must succeed.
+fn run(cpu: &mut CPU, opcode: u16) {
+    exec(cpu, opcode).unwrap();
+}
+
+/// Executes each of `opcodes` in turn.
+fn run_all(cpu: &mut CPU, opcodes: &[u16]) {
+    for opcode in opcodes { run(cpu, *opcode); }
+}
+
+/// Executes `opcode` and returns whether it skipped the next instruction.
+fn skips(cpu: &mut CPU, opcode: u16) -> bool {
+    let pc = cpu.pc;
+    run(cpu, opcode);
+    cpu.pc == pc + 4
+}
+
+/// The lit pixels of the given plane, as (x, y) pairs.
+fn lit(cpu: &CPU, plane: u8) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+    for (y, row) in cpu.screen.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            if pixel & plane != 0 { pixels.push((x, y)); }
+        }
+    }
+    pixels
+}
+
+#[test]
+fn clear_blanks_the_selected_planes() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.screen[0][0] = 0b11;
+    cpu.screen[5][7] = 0b01;
+    run(&mut cpu, 0x00E0);
+    assert_eq!(cpu.screen[0][0], 0b10);
+    assert_eq!(cpu.screen[5][7], 0);
+
+    run_all(&mut cpu, &[0xF301, 0x00E0]);
+    assert!(lit(&cpu, 0b11).is_empty());
+}
+
+#[test]
+fn call_and_return() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    run(&mut cpu, 0x2345);
+    assert_eq!((cpu.pc, cpu.sp, cpu.stack[0]), (0x345, 1, 0x202));
+    run(&mut cpu, 0x00EE);
+    assert_eq!((cpu.pc, cpu.sp), (0x202, 0));
+}
+
+#[test]
+fn stack_holds_sixteen_calls() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    for depth in 0..16 {
+        run(&mut cpu, 0x2400 + depth * 2);
+    }
+    assert_eq!(cpu.sp, 16);
+    assert_eq!(exec(&mut cpu, 0x2500), Err(CpuError::StackOverflow { pc: 0x41E, opcode: 0x2500 }));
+    // A fault leaves the CPU pointing at the instruction
+    assert_eq!((cpu.pc, cpu.sp), (0x41E, 16));
+
+    for _ in 0..16 { run(&mut cpu, 0x00EE); }
+    assert_eq!((cpu.pc, cpu.sp), (0x202, 0));
+    assert_eq!(exec(&mut cpu, 0x00EE), Err(CpuError::StackUnderflow { pc: 0x202, opcode: 0x00EE }));
+}
+
+#[test]
+fn scrolling() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    run(&mut cpu, 0x00FF);
+    cpu.screen[10][10] = 1;
+    run(&mut cpu, 0x00C3);
+    assert_eq!(lit(&cpu, 1), vec![(10, 13)]);
+    run(&mut cpu, 0x00FB);
+    assert_eq!(lit(&cpu, 1), vec![(14, 13)]);
+    run(&mut cpu, 0x00FC);
+    assert_eq!(lit(&cpu, 1), vec![(10, 13)]);
+    run(&mut cpu, 0x00D2);
+    assert_eq!(lit(&cpu, 1), vec![(10, 11)]);
+
+    // Pixels scrolled off the edge are lost
+    run(&mut cpu, 0x00CF);
+    run(&mut cpu, 0x00CF);
+    run(&mut cpu, 0x00CF);
+    run(&mut cpu, 0x00CF);
+    assert!(lit(&cpu, 1).is_empty());
+}
+
+#[test]
+fn scrolling_only_moves_the_selected_planes() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.screen[0][0] = 0b11;
+    run_all(&mut cpu, &[0xF201, 0x00C1]);
+    assert_eq!(cpu.screen[0][0], 0b01);
+    assert_eq!(cpu.screen[1][0], 0b10);
+}
+
+#[test]
+fn exit_stops_execution() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    run(&mut cpu, 0x00FD);
+    assert!(cpu.has_exited());
+    assert_eq!(exec(&mut cpu, 0x6001), Ok(StepOutcome::Exited));
+    assert_eq!(cpu.registers[0], 0);
+}
+
+#[test]
+fn resolution_changes_clear_the_screen() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.screen[0][0] = 1;
+    run(&mut cpu, 0x00FF);
+    assert!(cpu.hires);
+    assert_eq!((cpu.screen_width(), cpu.screen_height()), (128, 64));
+    assert!(lit(&cpu, 1).is_empty());
+
+    cpu.screen[0][0] = 1;
+    run(&mut cpu, 0x00FE);
+    assert!(!cpu.hires);
+    assert_eq!((cpu.screen_width(), cpu.screen_height()), (64, 32));
+    assert!(lit(&cpu, 1).is_empty());
+}
+
+#[test]
+fn jump() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    run(&mut cpu, 0x1ABC);
+    assert_eq!(cpu.pc, 0xABC);
+}
+
+#[test]
+fn skips_comparing_with_a_constant() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[3] = 0x42;
+    assert!(skips(&mut cpu, 0x3342));
+    assert!(!skips(&mut cpu, 0x3343));
+    assert!(!skips(&mut cpu, 0x4342));
+    assert!(skips(&mut cpu, 0x4343));
+}
+
+#[test]
+fn skips_comparing_registers() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[1] = 7;
+    cpu.registers[2] = 7;
+    cpu.registers[3] = 8;
+    assert!(skips(&mut cpu, 0x5120));
+    assert!(!skips(&mut cpu, 0x5130));
+    assert!(!skips(&mut cpu, 0x9120));
+    assert!(skips(&mut cpu, 0x9130));
+}
+
+#[test]
+fn skipping_steps_over_long_instructions() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.memory[0x202..0x206].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+    run(&mut cpu, 0x3000);
+    assert_eq!(cpu.pc, 0x206);
+}
+
+#[test]
+fn register_ranges() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.registers[2..5].copy_from_slice(&[1, 2, 3]);
+    run(&mut cpu, 0x5242);
+    assert_eq!(&cpu.memory[0x300..0x303], &[1, 2, 3]);
+    assert_eq!(cpu.i, 0x300);
+
+    // Reversed ranges run backwards
+    run(&mut cpu, 0x5A83);
+    assert_eq!(&cpu.registers[8..11], &[3, 2, 1]);
+    assert_eq!(cpu.i, 0x300);
+}
+
+#[test]
+fn load_and_add_constants() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    run(&mut cpu, 0x6AFE);
+    assert_eq!(cpu.registers[0xA], 0xFE);
+    run(&mut cpu, 0x7A03);
+    assert_eq!(cpu.registers[0xA], 0x01);
+    // 7xkk never changes VF, even on overflow
+    assert_eq!(cpu.registers[0xF], 0);
+}
+
+#[test]
+fn copy_register() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[4] = 0x99;
+    run(&mut cpu, 0x8140);
+    assert_eq!(cpu.registers[1], 0x99);
+}
+
+#[test]
+fn logic_operations() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.registers[0xF] = 5;
+    cpu.registers[1] = 0b1100;
+    cpu.registers[2] = 0b1010;
+    run(&mut cpu, 0x8121);
+    assert_eq!(cpu.registers[1], 0b1110);
+    cpu.registers[1] = 0b1100;
+    run(&mut cpu, 0x8122);
+    assert_eq!(cpu.registers[1], 0b1000);
+    cpu.registers[1] = 0b1100;
+    run(&mut cpu, 0x8123);
+    assert_eq!(cpu.registers[1], 0b0110);
+    assert_eq!(cpu.registers[0xF], 5);
+}
+
+#[test]
+fn logic_operations_reset_vf_on_the_vip() {
+    for opcode in [0x8121, 0x8122, 0x8123] {
+        let mut cpu = new_cpu(Quirks::VIP);
+        cpu.registers[0xF] = 5;
+        run(&mut cpu, opcode);
+        assert_eq!(cpu.registers[0xF], 0);
+    }
+}
+
+#[test]
+fn add_sets_carry() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[1] = 0xFF;
+    cpu.registers[2] = 0x02;
+    run(&mut cpu, 0x8124);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0x01, 1));
+    run(&mut cpu, 0x8124);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0x03, 0));
+}
+
+#[test]
+fn subtract_sets_not_borrow() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[1] = 5;
+    cpu.registers[2] = 3;
+    run(&mut cpu, 0x8125);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (2, 1));
+    run(&mut cpu, 0x8125);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0xFF, 0));
+
+    // Equal values don't borrow
+    cpu.registers[1] = 3;
+    run(&mut cpu, 0x8125);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0, 1));
+
+    cpu.registers[1] = 3;
+    cpu.registers[2] = 5;
+    run(&mut cpu, 0x8127);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (2, 1));
+    cpu.registers[1] = 6;
+    run(&mut cpu, 0x8127);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0xFF, 0));
+}
+
+#[test]
+fn shifts_use_vy_on_the_vip() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[1] = 0;
+    cpu.registers[2] = 0b1000_0001;
+    run(&mut cpu, 0x8126);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0b0100_0000, 1));
+    run(&mut cpu, 0x812E);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0b0000_0010, 1));
+    cpu.registers[2] = 0b0100_0010;
+    run(&mut cpu, 0x8126);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0b0010_0001, 0));
+    run(&mut cpu, 0x812E);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0b1000_0100, 0));
+}
+
+#[test]
+fn shifts_ignore_vy_on_the_schip() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.registers[1] = 0b0000_0011;
+    cpu.registers[2] = 0xFF;
+    run(&mut cpu, 0x8126);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0b0000_0001, 1));
+    run(&mut cpu, 0x812E);
+    assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0b0000_0010, 0));
+}
+
+#[test]
+fn flag_wins_when_vf_is_the_destination() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[0xF] = 0xFF;
+    cpu.registers[1] = 0x02;
+    run(&mut cpu, 0x8F14);
+    assert_eq!(cpu.registers[0xF], 1);
+
+    cpu.registers[0xF] = 0x05;
+    cpu.registers[1] = 0x03;
+    run(&mut cpu, 0x8F15);
+    assert_eq!(cpu.registers[0xF], 1);
+    cpu.registers[0xF] = 0x03;
+    cpu.registers[1] = 0x05;
+    run(&mut cpu, 0x8F15);
+    assert_eq!(cpu.registers[0xF], 0);
+
+    cpu.registers[0xF] = 0x05;
+    cpu.registers[1] = 0x03;
+    run(&mut cpu, 0x8F17);
+    assert_eq!(cpu.registers[0xF], 0);
+
+    cpu.registers[1] = 0b0000_0010;
+    run(&mut cpu, 0x8F16);
+    assert_eq!(cpu.registers[0xF], 0);
+    cpu.registers[1] = 0b1000_0000;
+    run(&mut cpu, 0x8F1E);
+    assert_eq!(cpu.registers[0xF], 1);
+
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.registers[0xF] = 0b1000_0001;
+    run(&mut cpu, 0x8FF6);
+    assert_eq!(cpu.registers[0xF], 1);
+}
+
+#[test]
+fn load_i() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    run(&mut cpu, 0xA123);
+    assert_eq!(cpu.i, 0x123);
+}
+
+#[test]
+fn offset_jump() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[0] = 0x10;
+    cpu.registers[3] = 0x20;
+    run(&mut cpu, 0xB300);
+    assert_eq!(cpu.pc, 0x310);
+
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.registers[0] = 0x10;
+    cpu.registers[3] = 0x20;
+    run(&mut cpu, 0xB300);
+    assert_eq!(cpu.pc, 0x320);
+}
+
+#[test]
+fn random_values_are_masked() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.set_random_source(RandomSource::scripted(vec![0xAB, 0xFF]));
+    run(&mut cpu, 0xC0F0);
+    assert_eq!(cpu.registers[0], 0xA0);
+    run(&mut cpu, 0xC10F);
+    assert_eq!(cpu.registers[1], 0x0F);
+}
+
+#[test]
+fn vip_random_routine() {
+    // An interpreter whose second page counts up, so each call adds the low byte of R9 to the previous value
+    let interpreter: Vec<u8> = (0..=255).map(|_| 0).chain(0..=255).collect();
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.set_random_source(RandomSource::vip(0x12FF, &interpreter).unwrap());
+    let mut values = Vec::new();
+    for _ in 0..12 {
+        run(&mut cpu, 0xC0FF);
+        values.push(cpu.registers[0]);
+    }
+    // R9 carries into its high byte on the first call; the seventh call's sum carries into bit 7
+    assert_eq!(values, [0x1C, 0x2B, 0x43, 0x69, 0xA3, 0xFC, 0x83, 0xCF, 0x42, 0x70, 0xB7, 0x23]);
+
+    // The state is kept in snapshots, which can only be restored into a VIP source
+    let snapshot = cpu.snapshot();
+    run(&mut cpu, 0xC0FF);
+    let next = cpu.registers[0];
+    cpu.restore(&snapshot).unwrap();
+    run(&mut cpu, 0xC0FF);
+    assert_eq!(cpu.registers[0], next);
+    assert_eq!(CPU::new(Quirks::VIP).restore(&snapshot), Err(SnapshotError::InvalidField("random number generator")));
+    assert!(RandomSource::vip(0, &interpreter[1..]).is_err());
+}
+
+#[test]
+fn draw_and_collide() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.memory[0x300..0x302].copy_from_slice(&[0b1100_0000, 0b0100_0000]);
+    cpu.registers[1] = 3;
+    cpu.registers[2] = 4;
+    run(&mut cpu, 0xD122);
+    assert_eq!(lit(&cpu, 1), vec![(3, 4), (4, 4), (4, 5)]);
+    assert_eq!(cpu.registers[0xF], 0);
+
+    // Drawing again erases the sprite, reporting a collision
+    run(&mut cpu, 0xD122);
+    assert!(lit(&cpu, 1).is_empty());
+    assert_eq!(cpu.registers[0xF], 1);
+
+    // Only overlapping lit pixels collide
+    run(&mut cpu, 0xD121);
+    cpu.registers[1] = 5;
+    run(&mut cpu, 0xD121);
+    assert_eq!(cpu.registers[0xF], 0);
+    cpu.registers[1] = 4;
+    run(&mut cpu, 0xD121);
+    assert_eq!(cpu.registers[0xF], 1);
+}
+
+#[test]
+fn sprites_are_clipped_at_the_edge() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.memory[0x300..0x302].copy_from_slice(&[0xFF, 0xFF]);
+    cpu.registers[1] = 60;
+    cpu.registers[2] = 31;
+    run(&mut cpu, 0xD122);
+    assert_eq!(lit(&cpu, 1), vec![(60, 31), (61, 31), (62, 31), (63, 31)]);
+}
+
+#[test]
+fn sprites_wrap_without_clipping() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.memory[0x300..0x302].copy_from_slice(&[0b1000_0001, 0b1000_0001]);
+    cpu.registers[1] = 60;
+    cpu.registers[2] = 31;
+    run(&mut cpu, 0xD122);
+    assert_eq!(lit(&cpu, 1), vec![(3, 0), (60, 0), (3, 31), (60, 31)]);
+}
+
+#[test]
+fn sprite_positions_always_wrap() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.memory[0x300] = 0b1000_0000;
+    cpu.registers[1] = 64 + 5;
+    cpu.registers[2] = 32 + 6;
+    run(&mut cpu, 0xD121);
+    assert_eq!(lit(&cpu, 1), vec![(5, 6)]);
+}
+
+#[test]
+fn large_sprites() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    run(&mut cpu, 0x00FF);
+    for row in 0..16 { cpu.memory[0x300 + row * 2..0x302 + row * 2].copy_from_slice(&[0x80, 0x01]); }
+    run(&mut cpu, 0xD120);
+    let pixels = lit(&cpu, 1);
+    assert_eq!(pixels.len(), 32);
+    assert!(pixels.contains(&(0, 0)) && pixels.contains(&(15, 15)));
+}
+
+#[test]
+fn drawing_on_both_planes() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.memory[0x300..0x302].copy_from_slice(&[0b1000_0000, 0b0100_0000]);
+    run_all(&mut cpu, &[0xF301, 0xD011]);
+    assert_eq!(lit(&cpu, 1), vec![(0, 0)]);
+    assert_eq!(lit(&cpu, 2), vec![(1, 0)]);
+
+    run_all(&mut cpu, &[0xF201, 0xD011]);
+    assert_eq!(lit(&cpu, 2), vec![(0, 0), (1, 0)]);
+    assert_eq!(cpu.screen[0][0], 0b11);
+    assert_eq!(cpu.registers[0xF], 0);
+}
+
+#[test]
+fn draw_reads_within_memory() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.i = 0xFFFE;
+    assert!(matches!(exec(&mut cpu, 0xD013), Err(CpuError::MemoryOutOfBounds { .. })));
+}
+
+#[test]
+fn display_wait_ends_the_frame_after_drawing() {
+    let mut quirks = Quirks::VIP;
+    assert_eq!(exec(&mut new_cpu(quirks), 0xD011), Ok(StepOutcome::Executed));
+    quirks.display_wait = true;
+    let mut cpu = new_cpu(quirks);
+    assert_eq!(exec(&mut cpu, 0xD011), Ok(StepOutcome::DisplayWait));
+    assert_eq!(exec(&mut cpu, 0x6000), Ok(StepOutcome::Executed));
+}
+
+#[test]
+fn skips_on_keys() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[1] = 0xF;
+    assert!(!skips(&mut cpu, 0xE19E));
+    assert!(skips(&mut cpu, 0xE1A1));
+    cpu.update_key(U4::B1111, true);
+    assert!(skips(&mut cpu, 0xE19E));
+    assert!(!skips(&mut cpu, 0xE1A1));
+}
+
+#[test]
+fn timers() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[1] = 2;
+    cpu.registers[2] = 1;
+    run_all(&mut cpu, &[0xF115, 0xF218]);
+    assert_eq!((cpu.delay_timer(), cpu.sound_timer()), (2, 1));
+    cpu.tick();
+    run(&mut cpu, 0xF307);
+    assert_eq!(cpu.registers[3], 1);
+    cpu.tick();
+    cpu.tick();
+    assert_eq!((cpu.delay_timer(), cpu.sound_timer()), (0, 0));
+}
+
+#[test]
+fn wait_for_key_press() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    run(&mut cpu, 0xF30A);
+    assert_eq!(cpu.pc, 0x200);
+
+    // Key F counts
+    cpu.update_key(U4::B1111, true);
+    run(&mut cpu, 0xF30A);
+    assert_eq!((cpu.pc, cpu.registers[3]), (0x202, 0xF));
+}
+
+#[test]
+fn wait_for_key_release() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    run(&mut cpu, 0xF30A);
+    cpu.update_key(U4::B0101, true);
+    run(&mut cpu, 0xF30A);
+    assert_eq!(cpu.pc, 0x200);
+    cpu.update_key(U4::B0101, false);
+    run(&mut cpu, 0xF30A);
+    assert_eq!((cpu.pc, cpu.registers[3]), (0x202, 5));
+}
+
+#[test]
+fn keys_held_before_waiting_need_pressing_again() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.update_key(U4::B0010, true);
+    run(&mut cpu, 0xF30A);
+    run(&mut cpu, 0xF30A);
+    assert_eq!(cpu.pc, 0x200);
+    cpu.update_key(U4::B0010, false);
+    run(&mut cpu, 0xF30A);
+    cpu.update_key(U4::B0010, true);
+    run(&mut cpu, 0xF30A);
+    assert_eq!((cpu.pc, cpu.registers[3]), (0x202, 2));
+}
+
+#[test]
+fn long_load_i() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.memory[0x202..0x204].copy_from_slice(&[0xBE, 0xEF]);
+    run(&mut cpu, 0xF000);
+    assert_eq!((cpu.i, cpu.pc), (0xBEEF, 0x204));
+}
+
+#[test]
+fn audio_pattern_and_pitch() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    assert_eq!(cpu.audio_pattern(), None);
+    cpu.memory[0x300..0x310].copy_from_slice(&[0xAA; 16]);
+    run(&mut cpu, 0xF002);
+    assert_eq!(cpu.audio_pattern(), Some([0xAA; 16]));
+
+    cpu.registers[1] = 112;
+    run(&mut cpu, 0xF13A);
+    assert_eq!(cpu.pitch, 112);
+    assert_eq!(cpu.playback_rate(), 8000.0);
+}
+
+#[test]
+fn add_to_i() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[1] = 0x10;
+    run(&mut cpu, 0xF11E);
+    assert_eq!(cpu.i, 0x310);
+    assert_eq!(cpu.registers[0xF], 0);
+}
+
+#[test]
+fn font_addresses() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.registers[1] = 0xA;
+    run(&mut cpu, 0xF129);
+    assert_eq!(cpu.i, 50);
+    assert_eq!(&cpu.memory[50..55], &FONT[50..55]);
+    run(&mut cpu, 0xF130);
+    assert_eq!(cpu.i, BIG_FONT_ADDRESS as u16 + 100);
+    assert_eq!(&cpu.memory[cpu.i as usize..cpu.i as usize + 10], &BIG_FONT[100..110]);
+}
+
+#[test]
+fn binary_coded_decimal() {
+    for (value, digits) in [(0, [0, 0, 0]), (9, [0, 0, 9]), (10, [0, 1, 0]), (99, [0, 9, 9]), (100, [1, 0, 0]), (255, [2, 5, 5])] {
+        let mut cpu = new_cpu(Quirks::VIP);
+        cpu.registers[4] = value;
+        run(&mut cpu, 0xF433);
+        assert_eq!(&cpu.memory[0x300..0x303], &digits, "BCD of {}", value);
+        assert_eq!(cpu.i, 0x300);
+    }
+}
+
+#[test]
+fn store_and_load_registers() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    cpu.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+    run(&mut cpu, 0xF355);
+    assert_eq!(&cpu.memory[0x300..0x305], &[1, 2, 3, 4, 0]);
+    assert_eq!(cpu.i, 0x304);
+
+    cpu.i = 0x300;
+    cpu.registers = [0; 16];
+    run(&mut cpu, 0xF265);
+    assert_eq!(&cpu.registers[..4], &[1, 2, 3, 0]);
+    assert_eq!(cpu.i, 0x303);
+}
+
+#[test]
+fn index_increment_quirk() {
+    for (quirks, i) in [(Quirks::VIP, 0x303), (Quirks::CHIP48, 0x302), (Quirks::SCHIP, 0x300)] {
+        let mut cpu = new_cpu(quirks);
+        run(&mut cpu, 0xF255);
+        assert_eq!(cpu.i, i);
+        cpu.i = 0x300;
+        run(&mut cpu, 0xF265);
+        assert_eq!(cpu.i, i);
+    }
+}
+
+#[test]
+fn store_and_load_all_registers() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    for (index, register) in cpu.registers.iter_mut().enumerate() { *register = index as u8 * 3; }
+    let registers = cpu.registers;
+    run(&mut cpu, 0xFF55);
+    cpu.registers = [0; 16];
+    run(&mut cpu, 0xFF65);
+    assert_eq!(cpu.registers, registers);
+}
+
+#[test]
+fn memory_accesses_are_checked() {
+    for opcode in [0xF033, 0xF355, 0xF365, 0xF002, 0x5032] {
+        let mut cpu = new_cpu(Quirks::XO_CHIP);
+        cpu.i = 0xFFFF;
+        let error = exec(&mut cpu, opcode).unwrap_err();
+        assert!(matches!(error, CpuError::MemoryOutOfBounds { pc: 0x200, .. }), "{:04X}: {:?}", opcode, error);
+    }
+}
+
+#[test]
+fn memory_is_4k_without_large_memory() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.i = 0xFFE;
+    assert!(matches!(exec(&mut cpu, 0xF255), Err(CpuError::MemoryOutOfBounds { address: 0x1000, .. })));
+    cpu.i = 0xFFE;
+    run(&mut cpu, 0xF155);
+}
+
+#[test]
+fn rom_size_depends_on_memory_size() {
+    for (quirks, max) in [(Quirks::VIP, 3584), (Quirks::CHIP48, 3584), (Quirks::SCHIP, 3584), (Quirks::XO_CHIP, 65024)] {
+        let mut cpu = CPU::new(quirks);
+        assert_eq!(cpu.load(&vec![0; max]), Ok(()));
+        assert_eq!(cpu.load(&vec![0; max + 1]), Err(CpuError::RomTooLarge { size: max + 1, max }));
+    }
+}
+
+#[test]
+fn rpl_flags() {
+    let mut cpu = new_cpu(Quirks::SCHIP);
+    cpu.registers[..3].copy_from_slice(&[7, 8, 9]);
+    run(&mut cpu, 0xF275);
+    cpu.registers = [0; 16];
+    run(&mut cpu, 0xF185);
+    assert_eq!(&cpu.registers[..3], &[7, 8, 0]);
+}
+
+#[test]
+fn illegal_opcodes_fault() {
+    let mut cpu = new_cpu(Quirks::VIP);
+    assert_eq!(exec(&mut cpu, 0x8008), Err(CpuError::IllegalOpcode { pc: 0x200, opcode: 0x8008 }));
+    assert_eq!(cpu.pc, 0x200);
+}
+
+#[test]
+fn quirk_bits_round_trip() {
+    for quirks in [Quirks::VIP, Quirks::CHIP48, Quirks::SCHIP, Quirks::XO_CHIP] {
+        assert_eq!(Quirks::from_bits(quirks.to_bits()), Some(quirks));
+    }
+    assert_eq!(Quirks::from_bits(1 << 9), None);
+}
+
+#[test]
+fn snapshots_round_trip() {
+    let mut cpu = new_cpu(Quirks::XO_CHIP);
+    cpu.memory[0x300] = 0xF0;
+    run_all(&mut cpu, &[0x6105, 0xD111, 0xF175, 0x2400]);
+    let snapshot = cpu.snapshot();
+
+    let mut restored = CPU::new(Quirks::XO_CHIP);
+    restored.restore(&snapshot).unwrap();
+    assert_eq!(restored.snapshot(), snapshot);
+    assert_eq!((restored.pc, restored.sp, restored.registers[1]), (0x400, 1, 5));
+    assert_eq!(&restored.rpl[..2], &[0, 5]);
+    assert_eq!(restored.restore(&snapshot[1..]), Err(SnapshotError::WrongSize { expected: SNAPSHOT_SIZE, found: SNAPSHOT_SIZE - 1 }));
+}
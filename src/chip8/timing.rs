@@ -0,0 +1,72 @@
+use super::{CPU, Instruction};
+
+/// Machine cycles in each 60Hz frame on the COSMAC VIP (8 clock cycles each, at 1.7609MHz).
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+/// Machine cycles of each frame left for the interpreter, after the display's DMA and interrupt routine.
+pub const VIP_EXECUTION_CYCLES: u32 = VIP_CYCLES_PER_FRAME - 1832;
+
+/// Machine cycles the VIP interpreter takes to fetch and decode an instruction, before executing it: the fetch loop
+/// is 33 one-byte 1802 instructions, plus the return from the instruction's routine.
+const FETCH_CYCLES: u32 = 68;
+
+/// Machine cycles the VIP interpreter's `00E0` routine takes. After 4 instructions of setup, it clears the 256 bytes of
+/// the screen one at a time, in a loop of six 1802 instructions (12 machine cycles) per byte; the last pass leaves the
+/// loop after four instructions.
+const CLEAR_CYCLES: u32 = 8 + 255 * 12 + 8;
+
+impl CPU{
+    /// Decodes the instruction at the program counter, without executing it.
+    pub fn next_instruction(&self) -> Option<Instruction> {
+        Instruction::decode(self.memory.get(self.pc as usize..)?)
+    }
+
+    /// The number of COSMAC VIP machine cycles the instruction at the program counter takes, including fetching and
+    /// decoding it, given the current registers. The costs are counted from the 1802 routine for each instruction in
+    /// the VIP's CHIP-8 interpreter, at 2 machine cycles per 1802 instruction; routines that loop (`00E0`, `Dxyn`,
+    /// `Fx33`, `Fx55` and `Fx65`) are charged for each pass through the loop. The wait for the next frame before
+    /// `Dxyn` draws is left to the scheduler. Instructions the VIP doesn't have are charged as the cheapest instruction.
+    pub fn vip_cycles(&self) -> u32 {
+        let v = |x: u8| self.registers[x as usize & 0xF];
+        // Skips take a little longer, as the program counter is advanced twice
+        let skip = |skipped: bool| if skipped { 2 } else { 0 };
+        let execute = match self.next_instruction() {
+            Some(Instruction::Clear) => CLEAR_CYCLES,
+            Some(Instruction::Return | Instruction::Jump(_)) => 23,
+            Some(Instruction::Call(_)) => 26,
+            Some(Instruction::SkipEqual(x, kk)) => 12 + skip(v(x) == kk),
+            Some(Instruction::SkipNotEqual(x, kk)) => 12 + skip(v(x) != kk),
+            Some(Instruction::SkipRegistersEqual(x, y)) => 16 + skip(v(x) == v(y)),
+            Some(Instruction::SkipRegistersNotEqual(x, y)) => 16 + skip(v(x) != v(y)),
+            Some(Instruction::SetRegister(..)) => 6,
+            Some(Instruction::AddToRegister(..)) => 10,
+            Some(Instruction::Copy(..)) => 12,
+            Some(Instruction::Or(..) | Instruction::And(..) | Instruction::Xor(..) | Instruction::Add(..)
+                | Instruction::Subtract(..) | Instruction::ShiftRight(..) | Instruction::SubtractFrom(..)
+                | Instruction::ShiftLeft(..)) => 44,
+            Some(Instruction::SetI(_)) => 12,
+            // Crossing into the next page of memory takes an extra carry
+            Some(Instruction::JumpOffset(nnn)) => 22 + skip((nnn & 0xFF) + v(0) as u16 > 0xFF),
+            Some(Instruction::Random(..)) => 36,
+            // Each row is one byte if the sprite is byte-aligned, or is shifted bit by bit across two bytes otherwise
+            Some(Instruction::Draw(x, _, n)) => {
+                let offset = v(x) as u32 % 8;
+                let row = if offset == 0 { 16 } else { 34 + 4 * offset };
+                26 + n as u32 * row
+            },
+            Some(Instruction::SkipKeyPressed(x)) => 16 + skip(self.keyboard[v(x) as usize & 0xF]),
+            Some(Instruction::SkipKeyNotPressed(x)) => 16 + skip(!self.keyboard[v(x) as usize & 0xF]),
+            Some(Instruction::GetDelay(_) | Instruction::WaitKey(_) | Instruction::SetDelay(_) | Instruction::SetSound(_)) => 10,
+            Some(Instruction::AddToI(_)) => 19,
+            Some(Instruction::Font(_)) => 20,
+            // Each digit is found by repeated subtraction
+            Some(Instruction::Bcd(x)) => {
+                let value = v(x) as u32;
+                84 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+            },
+            Some(Instruction::Store(x) | Instruction::Load(x)) => 14 + 14 * (x as u32 + 1),
+            _ => 6,
+        };
+        FETCH_CYCLES + execute
+    }
+}
@@ -0,0 +1,480 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::chip8::{CPU, CpuError, Instruction};
+use crate::disassembler::{self, Syntax};
+use crate::headless::{self, HeadlessRunner};
+
+/// An interactive debugger, controlled by commands typed at a terminal prompt.
+/// Supports breakpoints, stepping and inspecting the CPU. Used both by the SDL2 frontend and on its own, without a window.
+#[derive(Default)]
+pub struct Debugger{
+    breakpoints: Vec<Breakpoint>,
+    resume_pc: Option<u16>, // Breakpoints are ignored at this address once, so execution can continue past them
+    last_command: Option<Command>, // The last stepping command, repeated when an empty line is entered
+    interrupt: Arc<AtomicBool>, // Set from elsewhere (e.g., a Ctrl+C handler) to pause execution at the next instruction
+}
+
+/// Executes instructions on behalf of the debugger. Each frontend implements this with its own step path, so that
+/// instructions run from the prompt tick the timers and are recorded to movies just as when running normally.
+pub trait Target {
+    /// Executes one instruction, starting a new frame first if the current one has run all of its instructions.
+    /// Returns true if the program has exited.
+    fn step(&mut self, cpu: &mut CPU) -> Result<bool, CpuError>;
+}
+
+impl Target for HeadlessRunner {
+    fn step(&mut self, cpu: &mut CPU) -> Result<bool, CpuError> { self.run(cpu, 1) }
+}
+
+/// A condition under which execution is paused, checked before each instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Break when the program counter reaches the address.
+    Address(u16),
+    /// Break when the next opcode matches `value` in the bits set in `mask`, e.g., the pattern `D??F`.
+    Opcode { value: u16, mask: u16 },
+    /// Break when a register or memory location compares with a value, e.g., `v3 == 0x10`.
+    Condition { operand: Operand, comparison: Comparison, value: u16 },
+}
+
+/// A value that can be inspected by a conditional breakpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(u8),
+    I,
+    DelayTimer,
+    SoundTimer,
+    StackPointer,
+    Memory(u16),
+}
+
+/// A comparison used by a conditional breakpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison { Equal, NotEqual, Less, LessOrEqual, Greater, GreaterOrEqual }
+
+/// A command entered at the debugger prompt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Execute the given number of instructions.
+    Step(u32),
+    /// Execute one instruction, running a `2nnn` subroutine call to completion.
+    StepOver,
+    /// Run until the current subroutine returns.
+    StepOut,
+    /// Resume execution until a breakpoint is hit.
+    Continue,
+    /// Add a breakpoint.
+    Break(Breakpoint),
+    /// Remove the breakpoint with the given number.
+    Delete(usize),
+    /// List the breakpoints.
+    Breakpoints,
+    /// Print the registers, I, the program counter and the timers.
+    Registers,
+    /// Print the stack.
+    Stack,
+    /// Print the given number of bytes of memory, starting at the address.
+    Memory { start: u16, length: u16 },
+    /// Print the list of commands.
+    Help,
+    /// Stop the interpreter.
+    Quit,
+}
+
+/// What the frontend should do once the prompt has been closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    /// Continue running the program, checking breakpoints before each instruction.
+    Continue,
+    /// Stop the interpreter.
+    Quit,
+}
+
+const HELP: &str = "\
+Commands (numbers are decimal, or hexadecimal with a 0x prefix):
+  s, step [n]              execute n instructions (default 1)
+  n, next                  step over a subroutine call
+  o, out                   run until the current subroutine returns
+  c, continue              resume execution
+  b, break <addr>          break when PC reaches addr
+  b, break op <pattern>    break on an opcode pattern, ? is a wildcard (e.g., D??F)
+  b, break <x> <op> <val>  break on a condition, where x is v0-vf, i, dt, st, sp or [addr],
+                           and op is one of == != < <= > >=
+  d, delete <n>            delete breakpoint n
+  bl, breakpoints          list breakpoints
+  r, regs                  print registers, I, PC and timers
+  stack                    print the stack
+  m, mem <addr> [len]      print len bytes of memory (default 16)
+  h, help                  print this message
+  q, quit                  stop the interpreter
+An empty line repeats the previous step, next or out command.
+Press Ctrl+C to pause a running program, or a long step, next or out.";
+
+impl Debugger{
+    /// Creates a debugger with no breakpoints.
+    pub fn new() -> Self { Self::default() }
+
+    /// A flag which, once set, pauses execution before the next instruction (e.g., from a Ctrl+C handler).
+    /// It interrupts `continue`, as well as stepping commands that run for a long time.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> { Arc::clone(&self.interrupt) }
+
+    /// Returns true if execution has been interrupted since the last call.
+    pub fn interrupted(&self) -> bool { self.interrupt.swap(false, Ordering::SeqCst) }
+
+    /// Adds a breakpoint.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Returns true if execution should pause before the next instruction.
+    /// Breakpoints are skipped once at the address execution was resumed from.
+    pub fn should_break(&mut self, cpu: &CPU) -> bool {
+        if self.resume_pc.take() == Some(cpu.pc()) { return false; }
+        self.breakpoints.iter().any(|breakpoint| breakpoint.is_hit(cpu))
+    }
+
+    /// Runs the debugger without a window: the prompt is shown immediately, and execution continues from the prompt
+    /// until a breakpoint is hit, the program exits, the CPU faults or execution is interrupted, at which point the
+    /// prompt is shown again.
+    pub fn run<T: Target, R: BufRead, W: Write>(&mut self, target: &mut T, cpu: &mut CPU, input: &mut R, output: &mut W) -> io::Result<()> {
+        loop {
+            if self.prompt(target, cpu, input, output)? == Resume::Quit { return Ok(()); }
+
+            loop {
+                if self.interrupted() {
+                    writeln!(output, "Interrupted at 0x{:04X}", cpu.pc())?;
+                    break;
+                }
+                if self.should_break(cpu) {
+                    writeln!(output, "Breakpoint hit at 0x{:04X}", cpu.pc())?;
+                    break;
+                }
+                match target.step(cpu) {
+                    Ok(false) => { },
+                    Ok(true) => { writeln!(output, "Program exited")?; break; },
+                    Err(error) => { writeln!(output, "CPU fault: {}", error)?; break; },
+                }
+            }
+        }
+    }
+
+    /// Shows the prompt, executing commands until execution is continued or the interpreter is stopped.
+    /// Returns `Resume::Quit` if the input is closed.
+    /// Instructions run from the prompt are executed by `target`.
+    pub fn prompt<T: Target, R: BufRead, W: Write>(&mut self, target: &mut T, cpu: &mut CPU, input: &mut R, output: &mut W) -> io::Result<Resume> {
+        self.print_location(cpu, output)?;
+        loop {
+            write!(output, "(chip8) ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 { return Ok(Resume::Quit); }
+
+            let command = if line.trim().is_empty() {
+                match self.last_command { Some(command) => command, None => continue }
+            } else {
+                match Command::parse(&line) {
+                    Ok(command) => command,
+                    Err(msg) => { writeln!(output, "{}", msg)?; continue; },
+                }
+            };
+            if matches!(command, Command::Step(_) | Command::StepOver | Command::StepOut) {
+                self.last_command = Some(command);
+            }
+
+            match command {
+                Command::Continue => { self.resume_pc = Some(cpu.pc()); return Ok(Resume::Continue); },
+                Command::Quit => return Ok(Resume::Quit),
+                _ => {
+                    // An interrupt while waiting at the prompt shouldn't cut short the next command
+                    self.interrupted();
+                    self.execute(target, cpu, command, output)?;
+                },
+            }
+        }
+    }
+
+    /// Executes a command that doesn't leave the prompt.
+    fn execute<T: Target, W: Write>(&mut self, target: &mut T, cpu: &mut CPU, command: Command, output: &mut W) -> io::Result<()> {
+        match command {
+            Command::Step(count) => {
+                for _ in 0..count {
+                    if !self.step(target, cpu, output)? { break; }
+                    if self.interrupted() { writeln!(output, "Interrupted")?; break; }
+                }
+                self.print_location(cpu, output)?;
+            },
+            Command::StepOver => {
+                let is_call = opcode_at(cpu, cpu.pc()) & 0xF000 == 0x2000;
+                let (return_address, depth) = (cpu.pc().wrapping_add(2), cpu.sp());
+                if is_call {
+                    self.run_until(target, cpu, output, |cpu| cpu.pc() == return_address && cpu.sp() == depth)?;
+                } else {
+                    self.step(target, cpu, output)?;
+                }
+                self.print_location(cpu, output)?;
+            },
+            Command::StepOut => {
+                let depth = cpu.sp();
+                if depth == 0 {
+                    writeln!(output, "Not in a subroutine")?;
+                } else {
+                    self.run_until(target, cpu, output, |cpu| cpu.sp() < depth)?;
+                    self.print_location(cpu, output)?;
+                }
+            },
+            Command::Break(breakpoint) => {
+                self.breakpoints.push(breakpoint);
+                writeln!(output, "Breakpoint {}: {}", self.breakpoints.len() - 1, breakpoint)?;
+            },
+            Command::Delete(index) => {
+                if index < self.breakpoints.len() {
+                    let breakpoint = self.breakpoints.remove(index);
+                    writeln!(output, "Deleted breakpoint {}: {}", index, breakpoint)?;
+                } else {
+                    writeln!(output, "No breakpoint {}", index)?;
+                }
+            },
+            Command::Breakpoints => {
+                if self.breakpoints.is_empty() { writeln!(output, "No breakpoints")?; }
+                for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+                    writeln!(output, "{}: {}", index, breakpoint)?;
+                }
+            },
+            Command::Registers => {
+                for line in headless::state_lines(cpu).iter().take(2) { writeln!(output, "{}", line)?; }
+            },
+            Command::Stack => {
+                if cpu.sp() == 0 { writeln!(output, "Stack is empty")?; }
+                for (depth, address) in cpu.stack()[..cpu.sp() as usize].iter().enumerate().rev() {
+                    writeln!(output, "{:2}: 0x{:04X}", depth, address)?;
+                }
+            },
+            Command::Memory { start, length } => {
+                let memory = cpu.memory();
+                let end = (start as usize + length as usize).min(memory.len());
+                for row_start in (start as usize..end).step_by(16) {
+                    let row: Vec<String> = memory[row_start..end.min(row_start + 16)].iter().map(|byte| format!("{:02X}", byte)).collect();
+                    writeln!(output, "{:04X}: {}", row_start, row.join(" "))?;
+                }
+            },
+            Command::Help => writeln!(output, "{}", HELP)?,
+            Command::Continue | Command::Quit => { },
+        }
+        Ok(())
+    }
+
+    /// Executes one instruction, reporting if the program exits or the CPU faults.
+    /// Returns true if execution can continue.
+    fn step<T: Target, W: Write>(&mut self, target: &mut T, cpu: &mut CPU, output: &mut W) -> io::Result<bool> {
+        match target.step(cpu) {
+            Ok(false) => Ok(true),
+            Ok(true) => { writeln!(output, "Program exited")?; Ok(false) },
+            Err(error) => { writeln!(output, "CPU fault: {}", error)?; Ok(false) },
+        }
+    }
+
+    /// Executes instructions until `done` returns true, a breakpoint is hit, the program exits, the CPU faults or
+    /// execution is interrupted. At least one instruction is executed, so breakpoints at the current address don't
+    /// stop execution.
+    fn run_until<T: Target, W: Write, F: Fn(&CPU) -> bool>(&mut self, target: &mut T, cpu: &mut CPU, output: &mut W, done: F) -> io::Result<()> {
+        while self.step(target, cpu, output)? && !done(cpu) {
+            if self.interrupted() {
+                writeln!(output, "Interrupted at 0x{:04X}", cpu.pc())?;
+                break;
+            }
+            if self.breakpoints.iter().any(|breakpoint| breakpoint.is_hit(cpu)) {
+                writeln!(output, "Breakpoint hit at 0x{:04X}", cpu.pc())?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the address, value and mnemonic of the next instruction.
+    fn print_location<W: Write>(&self, cpu: &CPU, output: &mut W) -> io::Result<()> {
+        if cpu.has_exited() { return Ok(()); }
+        let mnemonic = match Instruction::decode(&cpu.memory()[cpu.pc() as usize..]) {
+            Some(instruction) => disassembler::format_instruction(&instruction, Syntax::Cowgod, &BTreeMap::new()),
+            None => "(illegal)".to_string(),
+        };
+        writeln!(output, "0x{:04X}: {:04X}  {}", cpu.pc(), opcode_at(cpu, cpu.pc()), mnemonic)
+    }
+}
+
+/// Reads the opcode at the given address, or 0 if it lies past the end of memory.
+fn opcode_at(cpu: &CPU, address: u16) -> u16 {
+    let memory = cpu.memory();
+    match (memory.get(address as usize), memory.get(address as usize + 1)) {
+        (Some(high), Some(low)) => (*high as u16) << 8 | *low as u16,
+        _ => 0,
+    }
+}
+
+impl Breakpoint {
+    /// Returns true if the CPU is about to execute an instruction this breakpoint applies to.
+    pub fn is_hit(&self, cpu: &CPU) -> bool {
+        match *self {
+            Breakpoint::Address(address) => cpu.pc() == address,
+            Breakpoint::Opcode { value, mask } => opcode_at(cpu, cpu.pc()) & mask == value,
+            Breakpoint::Condition { operand, comparison, value } => {
+                let actual = match operand {
+                    Operand::Register(x) => cpu.registers()[x as usize] as u16,
+                    Operand::I => cpu.i(),
+                    Operand::DelayTimer => cpu.delay_timer() as u16,
+                    Operand::SoundTimer => cpu.sound_timer() as u16,
+                    Operand::StackPointer => cpu.sp() as u16,
+                    Operand::Memory(address) => cpu.memory().get(address as usize).copied().unwrap_or(0) as u16,
+                };
+                match comparison {
+                    Comparison::Equal => actual == value,
+                    Comparison::NotEqual => actual != value,
+                    Comparison::Less => actual < value,
+                    Comparison::LessOrEqual => actual <= value,
+                    Comparison::Greater => actual > value,
+                    Comparison::GreaterOrEqual => actual >= value,
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Address(address) => write!(f, "PC == 0x{:04X}", address),
+            Breakpoint::Opcode { value, mask } => {
+                let pattern: String = (0..4).rev().map(|digit| {
+                    if (mask >> (digit * 4)) & 0xF == 0 { '?' } else { std::char::from_digit(((value >> (digit * 4)) & 0xF) as u32, 16).unwrap().to_ascii_uppercase() }
+                }).collect();
+                write!(f, "opcode {}", pattern)
+            },
+            Breakpoint::Condition { operand, comparison, value } => write!(f, "{} {} 0x{:X}", operand, comparison, value),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(x) => write!(f, "V{:X}", x),
+            Operand::I => write!(f, "I"),
+            Operand::DelayTimer => write!(f, "DT"),
+            Operand::SoundTimer => write!(f, "ST"),
+            Operand::StackPointer => write!(f, "SP"),
+            Operand::Memory(address) => write!(f, "[0x{:04X}]", address),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl Command {
+    /// Parses a line entered at the prompt.
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let argument = |index: usize| words.get(index).copied().ok_or("Missing argument; type 'help' for usage".to_string());
+        match words.first().copied().unwrap_or("") {
+            "s" | "step" => Ok(Command::Step(if words.len() > 1 { parse_number(argument(1)?)? } else { 1 })),
+            "n" | "next" => Ok(Command::StepOver),
+            "o" | "out" | "finish" => Ok(Command::StepOut),
+            "c" | "continue" => Ok(Command::Continue),
+            "b" | "break" => Breakpoint::parse(&words[1..]).map(Command::Break),
+            "d" | "delete" => Ok(Command::Delete(parse_number(argument(1)?)?)),
+            "bl" | "breakpoints" => Ok(Command::Breakpoints),
+            "r" | "regs" | "registers" => Ok(Command::Registers),
+            "stack" | "bt" => Ok(Command::Stack),
+            "m" | "mem" | "memory" => Ok(Command::Memory {
+                start: parse_number(argument(1)?)?,
+                length: if words.len() > 2 { parse_number(argument(2)?)? } else { 16 },
+            }),
+            "h" | "help" | "?" => Ok(Command::Help),
+            "q" | "quit" | "exit" => Ok(Command::Quit),
+            other => Err(format!("Unknown command '{}'; type 'help' for usage", other)),
+        }
+    }
+}
+
+impl Breakpoint {
+    /// Parses the arguments of a `break` command: an address, `op <pattern>`, or `<operand> <comparison> <value>`.
+    pub fn parse(words: &[&str]) -> Result<Breakpoint, String> {
+        match words {
+            [address] => Ok(Breakpoint::Address(parse_number(address)?)),
+            ["op", pattern] => {
+                let digits: Vec<char> = pattern.trim_start_matches("0x").chars().collect();
+                if digits.len() != 4 { return Err(format!("Opcode pattern '{}' must be four hex digits or ?", pattern)); }
+                let (mut value, mut mask) = (0u16, 0u16);
+                for digit in digits {
+                    value <<= 4;
+                    mask <<= 4;
+                    match digit {
+                        '?' | 'x' | 'X' | '_' => { },
+                        _ => {
+                            value |= digit.to_digit(16).ok_or(format!("Invalid opcode pattern '{}'", pattern))? as u16;
+                            mask |= 0xF;
+                        },
+                    }
+                }
+                Ok(Breakpoint::Opcode { value, mask })
+            },
+            [operand, comparison, value] => Ok(Breakpoint::Condition {
+                operand: parse_operand(operand)?,
+                comparison: parse_comparison(comparison)?,
+                value: parse_number(value)?,
+            }),
+            _ => Err("Usage: break <addr> | break op <pattern> | break <x> <op> <val>".to_string()),
+        }
+    }
+}
+
+/// Parses a register, timer or `[addr]` memory location.
+fn parse_operand(word: &str) -> Result<Operand, String> {
+    let lower = word.to_lowercase();
+    match lower.as_str() {
+        "i" => Ok(Operand::I),
+        "dt" => Ok(Operand::DelayTimer),
+        "st" => Ok(Operand::SoundTimer),
+        "sp" => Ok(Operand::StackPointer),
+        _ if lower.starts_with('[') && lower.ends_with(']') => Ok(Operand::Memory(parse_number(&lower[1..lower.len() - 1])?)),
+        _ if lower.len() == 2 && lower.starts_with('v') => u8::from_str_radix(&lower[1..], 16)
+            .map(Operand::Register).map_err(|_| format!("Unknown register '{}'", word)),
+        _ => Err(format!("Unknown operand '{}'", word)),
+    }
+}
+
+/// Parses a comparison operator.
+fn parse_comparison(word: &str) -> Result<Comparison, String> {
+    match word {
+        "==" | "=" => Ok(Comparison::Equal),
+        "!=" => Ok(Comparison::NotEqual),
+        "<" => Ok(Comparison::Less),
+        "<=" => Ok(Comparison::LessOrEqual),
+        ">" => Ok(Comparison::Greater),
+        ">=" => Ok(Comparison::GreaterOrEqual),
+        _ => Err(format!("Unknown comparison '{}'", word)),
+    }
+}
+
+/// Parses a decimal number, or a hexadecimal number prefixed with `0x`.
+fn parse_number<T: TryFrom<u64>>(word: &str) -> Result<T, String> {
+    let parsed = match word.strip_prefix("0x").or(word.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => word.parse(),
+    };
+    parsed.ok().and_then(|number| T::try_from(number).ok()).ok_or(format!("Invalid number '{}'", word))
+}
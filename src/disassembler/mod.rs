@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use crate::chip8::Instruction;
+
+/// The assembly syntax used for mnemonics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// The syntax from Cowgod's technical reference, e.g., `LD V1, 0x20`.
+    Cowgod,
+    /// The syntax of the Octo assembler, e.g., `v1 := 0x20`.
+    Octo,
+}
+
+impl Syntax {
+    /// Looks up a syntax by name (`cowgod` or `octo`).
+    pub fn from_name(name: &str) -> Option<Syntax> {
+        match name.to_lowercase().as_str() {
+            "cowgod" => Some(Syntax::Cowgod),
+            "octo" => Some(Syntax::Octo),
+            _ => None,
+        }
+    }
+
+    /// The character that starts a comment.
+    fn comment(&self) -> char {
+        match self { Syntax::Cowgod => ';', Syntax::Octo => '#' }
+    }
+}
+
+impl Default for Syntax {
+    /// Defaults to the Cowgod syntax, which the assembler reads back.
+    fn default() -> Self { Syntax::Cowgod }
+}
+
+/// Column at which the address/opcode annotation starts.
+const ANNOTATION_COLUMN: usize = 32;
+
+/// Maximum number of data bytes written on one line.
+const BYTES_PER_LINE: usize = 8;
+
+/// Disassembles `data`, which is loaded at address `origin` and starts executing there.
+/// Code is separated from data by following every path of execution from the origin; bytes that are never reached
+/// are written as data. Targets of jumps, calls and `Annn` loads are given labels, and every line is annotated
+/// with its address and raw bytes.
+pub fn disassemble(data: &[u8], origin: u16, syntax: Syntax) -> String {
+    let code = find_code(data, origin);
+    let labels = infer_labels(data, origin, &code);
+
+    let mut output = String::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let address = origin as usize + offset;
+        if let Some(label) = labels.get(&(address as u16)) {
+            match syntax {
+                Syntax::Cowgod => output.push_str(&format!("{}:\n", label)),
+                Syntax::Octo => output.push_str(&format!(": {}\n", label)),
+            }
+        }
+
+        let (text, size) = match code.get(&offset) {
+            Some(instruction) => (format_instruction(instruction, syntax, &labels), instruction.size()),
+            None => {
+                // Data runs until the next code, label or the end of the line
+                let mut size = 1;
+                while size < BYTES_PER_LINE && offset + size < data.len() && !code.contains_key(&(offset + size))
+                    && !labels.contains_key(&((address + size) as u16)) {
+                    size += 1;
+                }
+                (format_data(&data[offset..offset + size], syntax), size)
+            },
+        };
+
+        let raw: String = data[offset..offset + size].iter().map(|byte| format!("{:02X}", byte)).collect();
+        output.push_str(&format!("    {:width$}{} 0x{:03X}: {}\n", text, syntax.comment(), address, raw, width = ANNOTATION_COLUMN - 4));
+        offset += size;
+    }
+    output
+}
+
+/// Finds the instructions reachable from the start of `data`, keyed by offset.
+/// Execution is followed through fall-through, skips, jumps and calls. `Bnnn` jumps can't be followed, as their
+/// target depends on a register.
+fn find_code(data: &[u8], origin: u16) -> BTreeMap<usize, Instruction> {
+    let mut code: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut claimed = vec![false; data.len()]; // Bytes belonging to an instruction
+    let mut pending: Vec<usize> = vec![0];
+
+    while let Some(offset) = pending.pop() {
+        if offset >= data.len() || code.contains_key(&offset) { continue; }
+        let instruction = match Instruction::decode(&data[offset..]) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        // Instructions overlapping one already found are treated as data
+        let end = offset + instruction.size();
+        if claimed[offset..end].iter().any(|byte| *byte) { continue; }
+        claimed[offset..end].iter_mut().for_each(|byte| *byte = true);
+        code.insert(offset, instruction);
+
+        if instruction.falls_through() { pending.push(end); }
+        if instruction.is_skip() {
+            let skipped = Instruction::decode(data.get(end..).unwrap_or(&[])).map(|next| next.size()).unwrap_or(2);
+            pending.push(end + skipped);
+        }
+        match instruction {
+            Instruction::Jump(target) | Instruction::Call(target) => {
+                if let Some(target) = (target as usize).checked_sub(origin as usize) { pending.push(target); }
+            },
+            _ => { },
+        }
+    }
+    code
+}
+
+/// Names the targets of jumps, calls and `I` loads that lie within `data`, at the start of a line.
+/// Call targets are named `sub_XXX`, jump targets `label_XXX` and `I` targets `data_XXX`.
+fn infer_labels(data: &[u8], origin: u16, code: &BTreeMap<usize, Instruction>) -> BTreeMap<u16, String> {
+    // Addresses falling inside an instruction can't be labelled
+    let mut inside = vec![false; data.len()];
+    for (offset, instruction) in code {
+        for byte in inside.iter_mut().skip(offset + 1).take(instruction.size() - 1) { *byte = true; }
+    }
+
+    let mut labels: BTreeMap<u16, String> = BTreeMap::new();
+    // Lower priority names first, so they are overwritten by higher priority ones
+    for prefix in ["data", "label", "sub"] {
+        for instruction in code.values() {
+            let target = match (prefix, instruction) {
+                ("data", Instruction::SetI(target)) | ("data", Instruction::SetILong(target)) => *target,
+                ("label", Instruction::Jump(target)) | ("label", Instruction::JumpOffset(target)) => *target,
+                ("sub", Instruction::Call(target)) => *target,
+                _ => continue,
+            };
+            let offset = match (target as usize).checked_sub(origin as usize) { Some(offset) => offset, None => continue };
+            if offset < data.len() && !inside[offset] {
+                labels.insert(target, format!("{}_{:03X}", prefix, target));
+            }
+        }
+    }
+    labels
+}
+
+/// Formats an address, using its label if it has one.
+fn format_address(address: u16, labels: &BTreeMap<u16, String>) -> String {
+    match labels.get(&address) {
+        Some(label) => label.clone(),
+        None => format!("0x{:03X}", address),
+    }
+}
+
+/// Formats raw data bytes.
+fn format_data(bytes: &[u8], syntax: Syntax) -> String {
+    let values: Vec<String> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+    match syntax {
+        Syntax::Cowgod => format!("DB {}", values.join(", ")),
+        Syntax::Octo => values.join(" "),
+    }
+}
+
+/// Formats one instruction in the given syntax. Addresses are replaced by their label, if they have one.
+pub fn format_instruction(instruction: &Instruction, syntax: Syntax, labels: &BTreeMap<u16, String>) -> String {
+    match syntax {
+        Syntax::Cowgod => format_cowgod(instruction, labels),
+        Syntax::Octo => format_octo(instruction, labels),
+    }
+}
+
+/// Formats one instruction in Cowgod's syntax.
+fn format_cowgod(instruction: &Instruction, labels: &BTreeMap<u16, String>) -> String {
+    let address = |nnn: u16| format_address(nnn, labels);
+    match *instruction {
+        Instruction::ScrollDown(n) => format!("SCD {}", n),
+        Instruction::ScrollUp(n) => format!("SCU {}", n),
+        Instruction::Clear => "CLS".to_string(),
+        Instruction::Return => "RET".to_string(),
+        Instruction::ScrollRight => "SCR".to_string(),
+        Instruction::ScrollLeft => "SCL".to_string(),
+        Instruction::Exit => "EXIT".to_string(),
+        Instruction::Lores => "LOW".to_string(),
+        Instruction::Hires => "HIGH".to_string(),
+        Instruction::Jump(nnn) => format!("JP {}", address(nnn)),
+        Instruction::Call(nnn) => format!("CALL {}", address(nnn)),
+        Instruction::SkipEqual(x, kk) => format!("SE V{:X}, 0x{:02X}", x, kk),
+        Instruction::SkipNotEqual(x, kk) => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        Instruction::SkipRegistersEqual(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::SaveRange(x, y) => format!("SAVE V{:X}, V{:X}", x, y),
+        Instruction::LoadRange(x, y) => format!("LOAD V{:X}, V{:X}", x, y),
+        Instruction::SetRegister(x, kk) => format!("LD V{:X}, 0x{:02X}", x, kk),
+        Instruction::AddToRegister(x, kk) => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        Instruction::Copy(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::Add(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::Subtract(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::ShiftRight(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::SubtractFrom(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::ShiftLeft(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SkipRegistersNotEqual(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::SetI(nnn) => format!("LD I, {}", address(nnn)),
+        Instruction::JumpOffset(nnn) => format!("JP V0, {}", address(nnn)),
+        Instruction::Random(x, kk) => format!("RND V{:X}, 0x{:02X}", x, kk),
+        Instruction::Draw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction::SkipKeyPressed(x) => format!("SKP V{:X}", x),
+        Instruction::SkipKeyNotPressed(x) => format!("SKNP V{:X}", x),
+        Instruction::SetILong(nnnn) => format!("LD I, LONG {}", match labels.get(&nnnn) { Some(label) => label.clone(), None => format!("0x{:04X}", nnnn) }),
+        Instruction::SelectPlanes(n) => format!("PLANE {}", n),
+        Instruction::LoadAudio => "AUDIO".to_string(),
+        Instruction::GetDelay(x) => format!("LD V{:X}, DT", x),
+        Instruction::WaitKey(x) => format!("LD V{:X}, K", x),
+        Instruction::SetDelay(x) => format!("LD DT, V{:X}", x),
+        Instruction::SetSound(x) => format!("LD ST, V{:X}", x),
+        Instruction::AddToI(x) => format!("ADD I, V{:X}", x),
+        Instruction::Font(x) => format!("LD F, V{:X}", x),
+        Instruction::BigFont(x) => format!("LD HF, V{:X}", x),
+        Instruction::Bcd(x) => format!("LD B, V{:X}", x),
+        Instruction::SetPitch(x) => format!("LD PITCH, V{:X}", x),
+        Instruction::Store(x) => format!("LD [I], V{:X}", x),
+        Instruction::Load(x) => format!("LD V{:X}, [I]", x),
+        Instruction::SaveFlags(x) => format!("LD R, V{:X}", x),
+        Instruction::LoadFlags(x) => format!("LD V{:X}, R", x),
+    }
+}
+
+/// Formats one instruction in Octo's syntax. Octo writes skips as the condition under which the next instruction runs,
+/// so e.g. `3xkk` (skip if Vx == kk) is written `if vx != kk then`.
+fn format_octo(instruction: &Instruction, labels: &BTreeMap<u16, String>) -> String {
+    let address = |nnn: u16| format_address(nnn, labels);
+    match *instruction {
+        Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+        Instruction::ScrollUp(n) => format!("scroll-up {}", n),
+        Instruction::Clear => "clear".to_string(),
+        Instruction::Return => "return".to_string(),
+        Instruction::ScrollRight => "scroll-right".to_string(),
+        Instruction::ScrollLeft => "scroll-left".to_string(),
+        Instruction::Exit => "exit".to_string(),
+        Instruction::Lores => "lores".to_string(),
+        Instruction::Hires => "hires".to_string(),
+        Instruction::Jump(nnn) => format!("jump {}", address(nnn)),
+        Instruction::Call(nnn) => format!(":call {}", address(nnn)),
+        Instruction::SkipEqual(x, kk) => format!("if v{:x} != 0x{:02X} then", x, kk),
+        Instruction::SkipNotEqual(x, kk) => format!("if v{:x} == 0x{:02X} then", x, kk),
+        Instruction::SkipRegistersEqual(x, y) => format!("if v{:x} != v{:x} then", x, y),
+        Instruction::SaveRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+        Instruction::LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+        Instruction::SetRegister(x, kk) => format!("v{:x} := 0x{:02X}", x, kk),
+        Instruction::AddToRegister(x, kk) => format!("v{:x} += 0x{:02X}", x, kk),
+        Instruction::Copy(x, y) => format!("v{:x} := v{:x}", x, y),
+        Instruction::Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+        Instruction::And(x, y) => format!("v{:x} &= v{:x}", x, y),
+        Instruction::Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+        Instruction::Add(x, y) => format!("v{:x} += v{:x}", x, y),
+        Instruction::Subtract(x, y) => format!("v{:x} -= v{:x}", x, y),
+        Instruction::ShiftRight(x, y) => format!("v{:x} >>= v{:x}", x, y),
+        Instruction::SubtractFrom(x, y) => format!("v{:x} =- v{:x}", x, y),
+        Instruction::ShiftLeft(x, y) => format!("v{:x} <<= v{:x}", x, y),
+        Instruction::SkipRegistersNotEqual(x, y) => format!("if v{:x} == v{:x} then", x, y),
+        Instruction::SetI(nnn) => format!("i := {}", address(nnn)),
+        Instruction::JumpOffset(nnn) => format!("jump0 {}", address(nnn)),
+        Instruction::Random(x, kk) => format!("v{:x} := random 0x{:02X}", x, kk),
+        Instruction::Draw(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        Instruction::SkipKeyPressed(x) => format!("if v{:x} -key then", x),
+        Instruction::SkipKeyNotPressed(x) => format!("if v{:x} key then", x),
+        Instruction::SetILong(nnnn) => format!("i := long {}", match labels.get(&nnnn) { Some(label) => label.clone(), None => format!("0x{:04X}", nnnn) }),
+        Instruction::SelectPlanes(n) => format!("plane {}", n),
+        Instruction::LoadAudio => "audio".to_string(),
+        Instruction::GetDelay(x) => format!("v{:x} := delay", x),
+        Instruction::WaitKey(x) => format!("v{:x} := key", x),
+        Instruction::SetDelay(x) => format!("delay := v{:x}", x),
+        Instruction::SetSound(x) => format!("buzzer := v{:x}", x),
+        Instruction::AddToI(x) => format!("i += v{:x}", x),
+        Instruction::Font(x) => format!("i := hex v{:x}", x),
+        Instruction::BigFont(x) => format!("i := bighex v{:x}", x),
+        Instruction::Bcd(x) => format!("bcd v{:x}", x),
+        Instruction::SetPitch(x) => format!("pitch := v{:x}", x),
+        Instruction::Store(x) => format!("save v{:x}", x),
+        Instruction::Load(x) => format!("load v{:x}", x),
+        Instruction::SaveFlags(x) => format!("saveflags v{:x}", x),
+        Instruction::LoadFlags(x) => format!("loadflags v{:x}", x),
+    }
+}
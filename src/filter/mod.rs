@@ -0,0 +1,101 @@
+use crate::chip8::CPU;
+use crate::palette::{Palette, Rgb};
+
+/// How the display filter reduces flicker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterMode {
+    /// Pixels are drawn exactly as they are on the CHIP-8 screen.
+    Off,
+    /// Pixels light up instantly but fade out over several frames, like the phosphor of a CRT.
+    Blend,
+    /// Pixels lit in either of the last two frames are drawn lit.
+    Or,
+}
+
+impl FilterMode {
+    /// Looks up a mode by name (`off`, `blend` or `or`).
+    pub fn from_name(name: &str) -> Option<FilterMode> {
+        match name.to_lowercase().as_str() {
+            "off" | "none" => Some(FilterMode::Off),
+            "blend" | "phosphor" => Some(FilterMode::Blend),
+            "or" => Some(FilterMode::Or),
+            _ => None,
+        }
+    }
+}
+
+/// Turns the CHIP-8 screen into the colours to display, reducing the flicker caused by games erasing and redrawing
+/// sprites with XOR. It keeps the previous frame, so must be applied once per displayed frame.
+pub struct DisplayFilter{
+    mode: FilterMode,
+    decay: f32, // Fraction of a pixel's brightness kept each frame after it goes out, in Blend mode
+    enabled: bool,
+    width: usize, // Size of the screen the history was recorded at; it is discarded when the resolution changes
+    height: usize,
+    previous_planes: Vec<u8>, // The previous frame's plane bitmasks, in Or mode
+    previous_colours: Vec<[f32; 3]>, // The previous frame's displayed colours, in Blend mode
+}
+
+impl DisplayFilter{
+    /// Creates a filter. `decay` is clamped to between 0 (no persistence) and 0.95.
+    pub fn new(mode: FilterMode, decay: f32) -> Self {
+        DisplayFilter {
+            mode, decay: decay.clamp(0.0, 0.95), enabled: mode != FilterMode::Off,
+            width: 0, height: 0, previous_planes: Vec::new(), previous_colours: Vec::new(),
+        }
+    }
+
+    /// The filter's mode, which applies while it is enabled.
+    pub fn mode(&self) -> FilterMode { self.mode }
+
+    /// Returns true if the filter is being applied.
+    pub fn is_enabled(&self) -> bool { self.enabled }
+
+    /// Switches the filter on or off. A filter created with [`FilterMode::Off`] is switched to Blend mode.
+    /// Returns true if the filter is now enabled.
+    pub fn toggle(&mut self) -> bool {
+        if self.mode == FilterMode::Off { self.mode = FilterMode::Blend; }
+        self.enabled = !self.enabled;
+        self.width = 0;
+        self.enabled
+    }
+
+    /// Reads the screen and returns the colour to display for each pixel, row by row, at the CPU's current resolution.
+    pub fn apply(&mut self, cpu: &CPU, palette: &Palette) -> Vec<Rgb> {
+        let (width, height) = (cpu.screen_width(), cpu.screen_height());
+        let planes: Vec<u8> = cpu.screen[..height].iter().flat_map(|row| row[..width].iter().map(|pixel| pixel & 0b11)).collect();
+        let mode = if self.enabled { self.mode } else { FilterMode::Off };
+
+        // The history is meaningless after a change of resolution
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.previous_planes = planes.clone();
+            self.previous_colours = planes.iter().map(|pixel| to_float(palette.colour(*pixel))).collect();
+        }
+
+        match mode {
+            FilterMode::Off => planes.iter().map(|pixel| palette.colour(*pixel)).collect(),
+            FilterMode::Or => {
+                let colours = planes.iter().zip(&self.previous_planes).map(|(pixel, previous)| palette.colour(pixel | previous)).collect();
+                self.previous_planes = planes;
+                colours
+            },
+            FilterMode::Blend => {
+                for (pixel, shown) in planes.iter().zip(self.previous_colours.iter_mut()) {
+                    let target = to_float(palette.colour(*pixel));
+                    // Lit pixels are shown at full brightness, and unlit ones fade towards the background
+                    *shown = if *pixel != 0 { target } else {
+                        [0, 1, 2].map(|channel| shown[channel] * self.decay + target[channel] * (1.0 - self.decay))
+                    };
+                }
+                self.previous_colours.iter().map(|colour| colour.map(|channel| channel.round() as u8)).collect()
+            },
+        }
+    }
+}
+
+/// Converts a colour to floating point, for blending.
+fn to_float(colour: Rgb) -> [f32; 3] {
+    colour.map(|channel| channel as f32)
+}
@@ -1,18 +1,183 @@
 use bobbin_bits::U4;
-use sdl2::{render::Canvas, video::Window, pixels::Color, event::Event, keyboard::Keycode, rect::Rect, audio::{AudioCallback, AudioSpecDesired, AudioDevice}};
-use crate::chip8::CPU;
+use sdl2::{render::Canvas, video::{FullscreenType, Window}, pixels::Color, event::Event, keyboard::{Keycode, Mod, Scancode}, rect::Rect, audio::{AudioCallback, AudioSpecDesired, AudioDevice}};
+use std::collections::HashMap;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::audio::{Synth, Tone, WavRecorder};
+use crate::capture::Capture;
+use crate::chip8::{CPU, CpuError, StepOutcome, SCREEN_WIDTH, SCREEN_HEIGHT};
+use crate::debugger::{Debugger, Resume, Target};
+use crate::filter::{DisplayFilter, FilterMode};
+use crate::keymap::{Binding, Hotkey, Keymap};
+use crate::movie::{Frame, Movie};
+use crate::palette::{Palette, Rgb};
+use crate::rewind::RewindBuffer;
+use crate::savestate::SaveSlots;
+use crate::scheduler::{Scheduler, Timing, TIMER_HZ};
+use crate::screenshot::Screenshots;
+use crate::viewport::{ScaleMode, Viewport};
 
 /// The default Windows graphics (and audio) layer, implemented using SDL2.
 pub struct WindowsSDL2{
-    cycles_per_frame: u8,
+    scheduler: Scheduler, // Decides how many frames to emulate, and how many instructions each runs, as real time passes
+    fault: Option<CpuError>, // Set when the CPU faults; execution is paused until a state is loaded
+    debugger: Option<Debugger>, // If attached, the Pause key (or F10) breaks into the debugger prompt in the terminal
+    break_requested: bool, // Set to break into the debugger before the next instruction
+    save_slots: Option<SaveSlots>, // If set, O and P save to and load from disk instead of memory
+    slot: usize, // The selected save slot, chosen with Ctrl and the number row
+    rewind: RewindBuffer, // Snapshots of recent frames, played backwards while Backspace is held
+    rewind_speed: usize, // Frames stepped back per frame while rewinding
+    rewinding: bool,
+    recording: Option<Movie>, // If set, the input of every frame is appended to this movie
+    playback: Option<(Movie, usize)>, // If set, input comes from this movie instead of the keyboard, from the given frame
+    replayed_cycles: Option<u8>, // Instructions the movie being replayed runs in the current frame
+    frame_executed: u32, // Instructions allowed to run so far in the current frame
+    instruction_due: bool, // Set once the current frame has allowed the next instruction to run, until it is executed
+    bindings: KeyBindings, // Host keys bound to the CHIP-8 keypad and hotkeys
+    palette: Palette, // Colours the screen is drawn in
+    filter: DisplayFilter, // Reduces flicker by blending or combining recent frames
+    window_scale: u32, // Initial size of the window, in window pixels per low-resolution CHIP-8 pixel
+    scale_mode: ScaleMode, // How the screen is scaled when the window is resized or made fullscreen
+    screenshots: Option<Screenshots>, // If set, the screenshot hotkey writes the screen to a PNG file
+    frames: u64, // Frames emulated so far, used to name screenshots
+    shown: Vec<Rgb>, // Colour of every pixel, from the display filter, drawn until the next frame is emulated
+    shown_frame: Option<u64>, // The frame `shown` was filtered on, or None if it must be filtered again
+    capture: Option<Capture>, // If set, every frame shown is captured
+    capture_scale: u32, // Image pixels per high-resolution CHIP-8 pixel in clips started with the capture hotkey
+    tone: Tone, // How the buzzer sounds
+    wav: Option<WavRecorder>, // If set, the buzzer is rendered to a WAV file, one 60th of a second per emulated frame
 }
 
-const PIXEL_SIZE: i32 = 20;
+const WINDOW_TITLE: &str = "CHIP-8 Interpreter";
+
+/// Number of frames of history kept for rewinding (30 seconds at 60fps).
+const REWIND_FRAMES: usize = 30 * 60;
+
+/// Default window scale, giving a 1280x640 window.
+const DEFAULT_WINDOW_SCALE: u32 = 20;
 
 impl WindowsSDL2{
     /// Creates a new instance of the Windows graphics (and audio) layer.
-    /// Defaults to 10 CPU cycles per frame, at 60fps.
-    pub fn new() -> Self {WindowsSDL2 { cycles_per_frame: 10 }}
+    /// Defaults to 600 instructions per second (10 per 60Hz frame), with the QWERTY keymap and white on black.
+    /// Rewinds at 2x speed, with no display filter. The window is 1280x640, scaled by whole numbers when resized.
+    pub fn new() -> Self {
+        WindowsSDL2 {
+            scheduler: Scheduler::new(600), fault: None, debugger: None, break_requested: false, save_slots: None, slot: 0,
+            rewind: RewindBuffer::new(REWIND_FRAMES), rewind_speed: 2, rewinding: false, recording: None, playback: None,
+            replayed_cycles: None, frame_executed: 0, instruction_due: false,
+            bindings: KeyBindings::resolve(&Keymap::default()).expect("built-in keymap uses valid key names"),
+            palette: Palette::default(), filter: DisplayFilter::new(FilterMode::Off, 0.6),
+            window_scale: DEFAULT_WINDOW_SCALE, scale_mode: ScaleMode::Integer, screenshots: None, frames: 0, shown: Vec::new(), shown_frame: None,
+            capture: None, capture_scale: 4, tone: Tone::default(), wav: None,
+        }
+    }
+
+    /// Sets the display filter, which the toggle hotkey switches on and off.
+    pub fn set_filter(&mut self, filter: DisplayFilter) {
+        self.filter = filter;
+    }
+
+    /// Sets the speed of the CPU, in instructions per second. The speed hotkeys change it by 60 (one per frame).
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.scheduler.set_instructions_per_second(instructions_per_second);
+    }
+
+    /// Sets how long each instruction takes, which defaults to [`Timing::Uniform`].
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.scheduler.set_timing(timing);
+    }
+
+    /// Sets the palette the screen is drawn in. The cycle hotkey steps from it through the built-in palettes.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Sets the initial size of the window, in window pixels per low-resolution CHIP-8 pixel (at least one).
+    pub fn set_window_scale(&mut self, scale: u32) {
+        self.window_scale = scale.max(1);
+    }
+
+    /// Sets how the screen is scaled to fill the window, which can be resized or made fullscreen.
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.scale_mode = mode;
+    }
+
+    /// Enables the screenshot hotkey, which writes the screen in the current palette to a PNG file.
+    pub fn set_screenshots(&mut self, screenshots: Screenshots) {
+        self.screenshots = Some(screenshots);
+    }
+
+    /// Starts capturing every frame shown. The capture hotkey stops it.
+    pub fn start_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    /// Sets the scale of clips started with the capture hotkey, in image pixels per high-resolution CHIP-8 pixel.
+    pub fn set_capture_scale(&mut self, scale: u32) {
+        self.capture_scale = scale.max(1);
+    }
+
+    /// Stops capturing, returning the capture to be finished.
+    pub fn take_capture(&mut self) -> Option<Capture> {
+        self.capture.take()
+    }
+
+    /// Sets how the buzzer sounds, which defaults to a 440Hz square wave at a quarter volume.
+    pub fn set_tone(&mut self, tone: Tone) {
+        self.tone = tone;
+    }
+
+    /// Renders the buzzer to a WAV file, in sync with the frames emulated.
+    pub fn record_audio(&mut self, wav: WavRecorder) {
+        self.wav = Some(wav);
+    }
+
+    /// Stops rendering the buzzer, returning the WAV file to be finished.
+    pub fn take_audio(&mut self) -> Option<WavRecorder> {
+        self.wav.take()
+    }
+
+    /// Replaces the keymap, which defaults to the QWERTY preset. Fails if any key names are unknown to SDL.
+    pub fn set_keymap(&mut self, keymap: &Keymap) -> Result<(), String> {
+        self.bindings = KeyBindings::resolve(keymap)?;
+        Ok(())
+    }
+
+    /// Sets how many frames are stepped back for each frame Backspace is held (at least one).
+    pub fn set_rewind_speed(&mut self, frames_per_frame: usize) {
+        self.rewind_speed = frames_per_frame.max(1);
+    }
+
+    /// Persists save states to disk. O and P then save to and load from the selected slot, which is chosen by holding
+    /// Ctrl and pressing 0-9 on the number row. Without this, a single save state is kept in memory.
+    pub fn set_save_slots(&mut self, save_slots: SaveSlots) {
+        self.save_slots = Some(save_slots);
+    }
+
+    /// Records the input of every frame into a movie, which should have been created with the CPU's seed and quirks.
+    /// Loading states and rewinding are disabled while recording, as they would make the movie impossible to replay.
+    pub fn record_movie(&mut self, movie: Movie) {
+        self.recording = Some(movie);
+    }
+
+    /// Stops recording, returning the movie recorded.
+    pub fn take_recording(&mut self) -> Option<Movie> {
+        self.recording.take()
+    }
+
+    /// Replays a movie: the keyboard is ignored, and each frame runs the keys and instructions recorded in the movie.
+    /// The CPU should have been set up with [`Movie::create_cpu`]. Control returns to the keyboard once it ends.
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.playback = Some((movie, 0));
+    }
+
+    /// Attaches a debugger. Execution breaks into its prompt before the first instruction, and again whenever a breakpoint
+    /// is hit, the CPU faults, or the Pause key (or F10) is pressed. The window is frozen while the prompt is open.
+    pub fn attach_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+        self.break_requested = true;
+    }
 
     /// Starts the interpreter. Responsible for handling inputs, driving the CPU and timers, and triggering audio.
     pub fn start_interpreter(&mut self, cpu: &mut CPU) -> Result<(), String> {
@@ -21,19 +186,22 @@ impl WindowsSDL2{
         let video_subsystem = sdl_context.video()?;
         let audio_subsystem = sdl_context.audio()?;
 
-        // Create window
-        let window = video_subsystem
-            .window("CHIP-8 Interpreter", 1280, 640)
+        // Create window, sized to fit the low-resolution screen at the window scale
+        let (columns, rows) = (SCREEN_WIDTH as u32 / 2, SCREEN_HEIGHT as u32 / 2);
+        let mut window = video_subsystem
+            .window(WINDOW_TITLE, columns * self.window_scale, rows * self.window_scale)
             .position_centered()
+            .resizable()
             .opengl()
             .build()
             .map_err(|e| e.to_string())?;
+        window.set_minimum_size(columns, rows).map_err(|e| e.to_string())?;
 
         // Create canvas which is mapped onto window
         let mut canvas = window.into_canvas().present_vsync()
             .build().map_err(|e| e.to_string())?;
 
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.set_draw_color(rgb(self.palette.colour(0)));
         canvas.clear();
         canvas.present();
 
@@ -44,148 +212,479 @@ impl WindowsSDL2{
             samples: None       // Default sample size
         };
 
-        // Initialise the audio source
+        // Initialise the audio source. It plays continuously, and the buzzer fades in and out within it, so that it
+        // starts and stops without clicking
         let mut device = audio_subsystem.open_playback(None, 
             &desired_spec, |spec| {
-            SquareWave {
-                phase_inc: 440.0 / spec.freq as f32,
-                phase: 0.0,
-                volume: 0.25
-            }
-        }).unwrap();
+            Buzzer { synth: Synth::new(self.tone.clone(), spec.freq as u32) }
+        })?;
+        device.resume();
 
-        // Get event handler and timer objects
+        // Get event handler
         let mut event_pump = sdl_context.event_pump()?;
-        let mut timer = sdl_context.timer()?;
-
-        // Define expected frame timing (60fps)
-        let frame_interval = 1_000 / 60;
-        let mut last_frame_time = timer.ticks();
     
         'running: loop {
             // Handle key presses
             for event in event_pump.poll_iter() {
                 match event {
                     Event::Quit { .. } => break 'running,
-                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
-                    // +/- adjust the game speed by changing the CPU cycles per frame
-                    Event::KeyDown{ keycode: Some(Keycode::Minus), .. } => {
-                        if self.cycles_per_frame > 0  { self.cycles_per_frame -= 1; } 
+                    // Ctrl and the number row select the save slot
+                    Event::KeyDown{ keycode: Some(keycode), keymod, .. }
+                        if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) && slot_number(keycode).is_some() => {
+                        self.slot = slot_number(keycode).unwrap();
+                        println!("Selected save slot {}.", self.slot);
+                    },
+                    Event::KeyDown{ keycode, scancode, repeat, .. } => match self.bindings.hotkey(keycode, scancode) {
+                        Some(Hotkey::Quit) => break 'running,
+                        // The speed hotkeys adjust the game speed by one instruction per frame
+                        Some(Hotkey::SpeedDown) => self.change_speed(-(TIMER_HZ as i32)),
+                        Some(Hotkey::SpeedUp) => self.change_speed(TIMER_HZ as i32),
+                        Some(Hotkey::DebugBreak) => self.break_requested = self.debugger.is_some(),
+                        Some(Hotkey::CyclePalette) => {
+                            self.palette = self.palette.next();
+                            self.shown_frame = None;
+                            println!("Palette: {}.", self.palette.name());
+                        },
+                        // The remaining hotkeys act on the first press only, not on key repeat
+                        Some(_) if repeat => { },
+                        Some(Hotkey::ToggleFullscreen) => {
+                            // Fullscreen uses the desktop resolution, so the screen is scaled like any other window size
+                            let window = canvas.window_mut();
+                            let fullscreen = match window.fullscreen_state() {
+                                FullscreenType::Off => FullscreenType::Desktop,
+                                _ => FullscreenType::Off,
+                            };
+                            if let Err(msg) = window.set_fullscreen(fullscreen) { println!("Unable to toggle fullscreen: {}.", msg); }
+                        },
+                        Some(Hotkey::ToggleFilter) => {
+                            let enabled = self.filter.toggle();
+                            self.shown_frame = None;
+                            println!("Display filter {}.", if enabled { "on" } else { "off" });
+                        },
+                        Some(Hotkey::Screenshot) => self.take_screenshot(cpu),
+                        Some(Hotkey::ToggleCapture) => self.toggle_capture(),
+                        Some(Hotkey::SaveState) => self.save_state(cpu),
+                        Some(Hotkey::LoadState) => {
+                            // Loading a state recovers from a fault
+                            let loaded = self.load_state(cpu);
+                            if loaded && self.fault.take().is_some() {
+                                let _ = canvas.window_mut().set_title(WINDOW_TITLE);
+                            }
+                        },
+                        // Holding the rewind hotkey plays the game backwards
+                        Some(Hotkey::Rewind) => self.rewinding = self.movie_allows("rewind"),
+                        // Otherwise, keys go to key handler
+                        None => self.handle_key(cpu, keycode, scancode, true),
+                    },
+                    Event::KeyUp{ keycode, scancode, .. } => match self.bindings.hotkey(keycode, scancode) {
+                        Some(Hotkey::Rewind) => self.rewinding = false,
+                        Some(_) => { },
+                        None => self.handle_key(cpu, keycode, scancode, false),
                     },
-                    Event::KeyDown{ keycode: Some(Keycode::Plus), .. } => self.cycles_per_frame += 1,
-                    Event::KeyDown{ keycode: Some(Keycode::Equals), .. } => self.cycles_per_frame += 1,
-                    // O and P save/load CPU state
-                    Event::KeyDown{ keycode: Some(Keycode::O), .. } => cpu.save_state(),
-                    Event::KeyDown{ keycode: Some(Keycode::P), .. } => cpu.load_state(),
-                    // Otherwise, other keys go to key handler
-                    Event::KeyDown { keycode: Some(keycode), .. } => self.handle_key(cpu, keycode, true),
-                    Event::KeyUp { keycode: Some(keycode), .. } => self.handle_key(cpu, keycode, false),
                     _ => { }
                 }
             }
             
-            // These statement will execute once per frame, or once roughly every 16.67ms, so we update the sound/delay timers
-            cpu.tick();
-            self.play_tone(cpu, &mut device);
-            
-            // At 60fps, the default ten instructions per frame equals 60 * 10 = 600 instructions per second
-            for _instruction in 0..self.cycles_per_frame{
-                cpu.step();
+            // Emulate the frames that have fallen due, so the timers run at exactly 60Hz whatever the display's refresh rate
+            let now = Instant::now();
+            let due = self.scheduler.advance(now);
+            for _frame in 0..due {
+                if !self.emulate_frame(cpu, &mut canvas, &mut device) { break 'running; }
             }
 
-            // Draw latest frame
+            // Draw at whatever rate the display refreshes at, as presenting waits for vsync. If vsync is unavailable,
+            // presenting returns at once, so wait for the next frame to fall due rather than spinning.
             self.draw_frame(cpu, &mut canvas);
-
-            // Calculate time since last frame
-            let current_frame_time = timer.ticks();
-            let delta = current_frame_time - last_frame_time;
-            
-            // Enforce frame timing: execution doesn't continue until 16.67ms has passed since last frame
-            if delta < frame_interval {
-                timer.delay(frame_interval - delta);
-                continue;
+            if now.elapsed() < Duration::from_millis(1) {
+                thread::sleep(self.scheduler.until_next_frame());
             }
-            last_frame_time = current_frame_time;
         }
         
         Ok(())
     }
 
-    /// Handles key presses. Keys are mapped to the top-left of the keyboard, following standard convention.
-    /// The `state` parameter determines whether the key is pressed / released.
-    fn handle_key(&mut self, cpu: &mut CPU, keycode: Keycode, state: bool) {
-        match keycode {
-            Keycode::Num1 => cpu.update_key(U4::B0001, state),
-            Keycode::Num2 => cpu.update_key(U4::B0010, state),
-            Keycode::Num3 => cpu.update_key(U4::B0011, state),
-            Keycode::Num4 => cpu.update_key(U4::B1100, state),
-            Keycode::Q => cpu.update_key(U4::B0100, state),
-            Keycode::W => cpu.update_key(U4::B0101, state),
-            Keycode::E => cpu.update_key(U4::B0110, state),
-            Keycode::R => cpu.update_key(U4::B1101, state),
-            Keycode::A => cpu.update_key(U4::B0111, state),
-            Keycode::S => cpu.update_key(U4::B1000, state),
-            Keycode::D => cpu.update_key(U4::B1001, state),
-            Keycode::F => cpu.update_key(U4::B1110, state),
-            Keycode::Z => cpu.update_key(U4::B1010, state),
-            Keycode::X => cpu.update_key(U4::B0000, state),
-            Keycode::C => cpu.update_key(U4::B1011, state),
-            Keycode::V => cpu.update_key(U4::B1111, state),
-            _ => return
+    /// Emulates one 60th of a second: ticks the timers and runs the frame's instructions, or steps back through the
+    /// history while rewinding. Returns false if the interpreter should stop.
+    fn emulate_frame(&mut self, cpu: &mut CPU, canvas: &mut Canvas<Window>, audio: &mut AudioDevice<Buzzer>) -> bool {
+        // While rewinding, the CPU is stepped back through its history instead of being run
+        if self.rewinding {
+            match self.rewind.rewind(self.rewind_speed, cpu) {
+                // Rewinding recovers from a fault
+                Ok(_) => if self.fault.take().is_some() { let _ = canvas.window_mut().set_title(WINDOW_TITLE); },
+                Err(error) => println!("Unable to rewind: {}.", error),
+            }
+            self.play_tone(cpu, audio);
+            self.end_frame(cpu);
+            return true;
+        }
+
+        self.begin_frame(cpu, audio);
+        while self.may_run(cpu) {
+            if self.debug_break_due(cpu) {
+                if !self.pause_for_debugger(cpu, canvas, audio) { return false; }
+                // Instructions stepped at the prompt may have used up this frame, and run through later ones
+                continue;
+            }
+            if self.fault.is_some() { break; }
+            match self.step(cpu) {
+                Ok(false) => { },
+                // Stop once the program has exited (SCHIP 00FD)
+                Ok(true) => return false,
+                Err(error) => {
+                    println!("CPU fault: {}. Execution paused; load a state with P or rewind with Backspace to continue.", error);
+                    self.set_fault(error, canvas);
+                    self.break_requested = self.debugger.is_some();
+                },
+            }
+        }
+        self.end_frame(cpu);
+        true
+    }
+
+    /// Starts a frame: ticks the timers (unless execution is paused by a fault), sounds the buzzer and sets the frame's
+    /// input. The scheduler, or the movie being replayed, then decides which instructions run in the frame.
+    fn begin_frame(&mut self, cpu: &mut CPU, audio: &mut AudioDevice<Buzzer>) {
+        // The sound/delay timers are ticked once per frame, i.e., at 60Hz
+        if self.fault.is_none() {
+            cpu.tick();
+        }
+        self.play_tone(cpu, audio);
+
+        // The frame's audio is rendered as the buzzer is heard, unless execution is paused by a fault
+        if let (Some(wav), true) = (self.wav.as_mut(), self.fault.is_none()) { wav.push_frame(cpu); }
+
+        self.replayed_cycles = self.begin_movie_frame(cpu);
+        self.frame_executed = 0;
+        self.instruction_due = false;
+        self.scheduler.begin_frame();
+    }
+
+    /// Returns true if the next instruction runs in the current frame. Once allowed, it stays due until executed.
+    fn may_run(&mut self, cpu: &CPU) -> bool {
+        if !self.instruction_due {
+            self.instruction_due = match self.replayed_cycles {
+                Some(cycles) => self.frame_executed < cycles as u32,
+                None => self.scheduler.may_run(cpu),
+            };
+            if self.instruction_due { self.frame_executed += 1; }
+        }
+        self.instruction_due
+    }
+
+    /// Executes the next instruction, counting it in the movie being recorded. Returns true if the program has exited.
+    fn step(&mut self, cpu: &mut CPU) -> Result<bool, CpuError> {
+        self.instruction_due = false;
+        match cpu.step()? {
+            outcome @ (StepOutcome::Executed | StepOutcome::DisplayWait) => {
+                if let Some(movie) = self.recording.as_mut() { movie.count_cycle(); }
+                // The rest of the frame's instructions wait for the next frame (the movie records this)
+                if outcome == StepOutcome::DisplayWait { self.scheduler.end_frame(); }
+                Ok(false)
+            },
+            StepOutcome::Exited => Ok(true),
+        }
+    }
+
+    /// Pauses execution after a CPU fault, until a state is loaded or the CPU is rewound.
+    fn set_fault(&mut self, error: CpuError, canvas: &mut Canvas<Window>) {
+        let _ = canvas.window_mut().set_title(&format!("{} - fault: {}", WINDOW_TITLE, error));
+        self.fault = Some(error);
+    }
+
+    /// Finishes a frame: records it for rewinding (unless rewinding, or paused by a fault), then captures it.
+    fn end_frame(&mut self, cpu: &CPU) {
+        if !self.rewinding && self.fault.is_none() {
+            self.rewind.push(cpu);
+        }
+        if let Some(capture) = self.capture.as_mut() { capture.push_frame(cpu, &self.palette); }
+        self.frames += 1;
+    }
+
+    /// Changes the speed by the given number of instructions per second. With VIP timing, the speed is fixed.
+    fn change_speed(&mut self, change: i32) {
+        if self.scheduler.timing() == Timing::Vip {
+            println!("The speed is set by the COSMAC VIP's timing, so can't be changed.");
+            return;
+        }
+        let speed = self.scheduler.instructions_per_second().saturating_add_signed(change);
+        self.scheduler.set_instructions_per_second(speed);
+        println!("Speed: {} instructions per second.", self.scheduler.instructions_per_second());
+    }
+
+    /// Writes the screen to a PNG file, if screenshots are enabled.
+    fn take_screenshot(&mut self, cpu: &CPU) {
+        match self.screenshots.as_ref().map(|screenshots| screenshots.save(cpu, &self.palette, self.frames)) {
+            Some(Ok(path)) => println!("Saved screenshot to {}.", path.display()),
+            Some(Err(error)) => println!("Unable to save screenshot: {}.", error),
+            None => println!("Screenshots are not enabled."),
+        }
+    }
+
+    /// Stops the current capture, or starts a new clip named like the screenshots if screenshots are enabled.
+    fn toggle_capture(&mut self) {
+        if let Some(capture) = self.capture.take() {
+            match capture.finish() {
+                Ok(frames) => println!("Stopped capture after {} frames.", frames),
+                Err(error) => println!("Unable to write capture: {}.", error),
+            }
+            return;
+        }
+        let Some(screenshots) = self.screenshots.as_ref() else { println!("Screenshots are not enabled."); return; };
+        let path = screenshots.clip_path(self.frames);
+        match Capture::create(&path, self.capture_scale, &self.palette) {
+            Ok(capture) => { println!("Capturing to {}.", path.display()); self.capture = Some(capture); },
+            Err(error) => println!("Unable to start capture: {}.", error),
+        }
+    }
+
+    /// Saves the CPU state to the selected slot on disk, or in memory if save slots haven't been set.
+    fn save_state(&mut self, cpu: &mut CPU) {
+        match &self.save_slots {
+            Some(save_slots) => match save_slots.save(self.slot, cpu) {
+                Ok(()) => println!("Saved state to slot {}.", self.slot),
+                // The file is named, so that an incompatible one can be moved aside
+                Err(error) => println!("Unable to save to slot {}: {} ({}).", self.slot, error, save_slots.path().display()),
+            },
+            None => cpu.save_state(),
+        }
+    }
+
+    /// Loads the CPU state from the selected slot on disk, or from memory if save slots haven't been set.
+    /// Returns true if a state was loaded.
+    fn load_state(&mut self, cpu: &mut CPU) -> bool {
+        if !self.movie_allows("load states") { return false; }
+        match &self.save_slots {
+            Some(save_slots) => match save_slots.load(self.slot, cpu) {
+                Ok(_) => { println!("Loaded state from slot {}.", self.slot); true },
+                Err(error) => { println!("Unable to load slot {}: {}.", self.slot, error); false },
+            },
+            None => { cpu.load_state(); true },
+        }
+    }
+
+    /// Returns false (with a message) if a movie is being recorded or replayed, as the given action would desynchronise it.
+    fn movie_allows(&self, action: &str) -> bool {
+        let allowed = self.recording.is_none() && self.playback.is_none();
+        if !allowed { println!("Unable to {} while a movie is being recorded or replayed.", action); }
+        allowed
+    }
+
+    /// Sets the keys for the next frame from the movie being replayed, or records them if a movie is being recorded.
+    /// Returns the number of instructions the movie runs in the frame, if one is being replayed.
+    fn begin_movie_frame(&mut self, cpu: &mut CPU) -> Option<u8> {
+        let mut cycles = None;
+        if let Some((movie, index)) = self.playback.as_mut() {
+            match movie.frames().get(*index).copied() {
+                Some(frame) => {
+                    cpu.set_key_mask(frame.keys);
+                    cycles = Some(frame.cycles);
+                    *index += 1;
+                },
+                None => {
+                    println!("Replay finished after {} frames; the keyboard is now active.", index);
+                    cpu.set_key_mask(0);
+                    self.playback = None;
+                },
+            }
+        }
+        if let Some(movie) = self.recording.as_mut() {
+            movie.push_frame(Frame { keys: cpu.key_mask(), cycles: 0 });
+        }
+        cycles
+    }
+
+    /// Returns true if the attached debugger (if any) should take over before the next instruction.
+    fn debug_break_due(&mut self, cpu: &CPU) -> bool {
+        match self.debugger.as_mut() {
+            Some(debugger) => std::mem::take(&mut self.break_requested) || debugger.interrupted() || debugger.should_break(cpu),
+            None => false,
+        }
+    }
+
+    /// Shows the latest frame, then hands control to the debugger prompt until execution is continued.
+    /// Returns false if the interpreter should stop. Instructions stepped at the prompt run through the same frames
+    /// as normal execution, but the time spent at the prompt isn't caught up on afterwards.
+    fn pause_for_debugger(&mut self, cpu: &mut CPU, canvas: &mut Canvas<Window>, audio: &mut AudioDevice<Buzzer>) -> bool {
+        // The screen may have changed mid-frame
+        self.shown_frame = None;
+        self.draw_frame(cpu, canvas);
+        audio.pause();
+
+        let mut debugger = match self.debugger.take() { Some(debugger) => debugger, None => return true };
+        let mut target = DebugTarget { frontend: self, canvas, audio };
+        let resume = match debugger.prompt(&mut target, cpu, &mut io::stdin().lock(), &mut io::stdout()) {
+            Ok(Resume::Continue) => true,
+            Ok(Resume::Quit) => false,
+            Err(error) => { println!("Debugger error: {}", error); false },
         };
+        self.debugger = Some(debugger);
+        self.shown_frame = None;
+        self.scheduler.reset();
+        resume
+    }
+
+    /// Handles presses of keys bound to the CHIP-8 keypad. Ignored while a movie is being replayed.
+    /// The `state` parameter determines whether the key is pressed / released.
+    fn handle_key(&mut self, cpu: &mut CPU, keycode: Option<Keycode>, scancode: Option<Scancode>, state: bool) {
+        if self.playback.is_some() { return; }
+        if let Some(key) = self.bindings.key(keycode, scancode) {
+            cpu.update_key(U4::from(key), state);
+        }
     }
 
     /// Draws the next frame to the screen.
     fn draw_frame(&mut self, cpu: &CPU, canvas: &mut Canvas<Window>) {
-        // Clear the screen.
-        canvas.set_draw_color(Color::RGB(0,0, 0));
+        // The screen is laid out afresh every frame, so follows resizes of the window and changes of resolution.
+        let (window_width, window_height) = canvas.output_size().unwrap_or((0, 0));
+        let viewport = Viewport::new(window_width, window_height, cpu.screen_width(), cpu.screen_height(), self.scale_mode);
+
+        // Clear the window, leaving black bars around the screen, then clear the screen to the background colour.
+        canvas.set_draw_color(Color::BLACK);
         canvas.clear();
+        let background = self.palette.colour(0);
+        canvas.set_draw_color(rgb(background));
+        let _ = canvas.fill_rect(Rect::new(viewport.x, viewport.y, viewport.width, viewport.height));
 
-        // For now, pixels are white.
-        canvas.set_draw_color(Color::RGB(255,255, 255));
-        
-        // Iterate over the 2D array storing the screen state, and draw a pixel if the corresponding value is set to true.
-        for x in 0..64{
-            for y in 0..32{
-                if cpu.screen[y][x] { 
-                    let _ = canvas.fill_rect(Rect::new(
-                        x as i32 * PIXEL_SIZE,
-                        y as i32 * PIXEL_SIZE,
-                        PIXEL_SIZE as u32,
-                        PIXEL_SIZE as u32,
-                    ));
-                }
+        // The filter gives the colour of every pixel, row by row; background pixels are left as cleared.
+        // It is applied once per emulated frame, so that its blending doesn't depend on the display's refresh rate.
+        if self.shown_frame != Some(self.frames) {
+            self.shown = self.filter.apply(cpu, &self.palette);
+            self.shown_frame = Some(self.frames);
+        }
+        for (index, colour) in self.shown.iter().enumerate() {
+            if *colour != background {
+                let (x, y, width, height) = viewport.pixel(index % cpu.screen_width(), index / cpu.screen_width());
+                canvas.set_draw_color(rgb(*colour));
+                let _ = canvas.fill_rect(Rect::new(x, y, width, height));
             }
         }
         canvas.present();
     }
 
-    /// Turns on/off the buzzer based on the value of the sound timer.
-    fn play_tone(&mut self, cpu: &CPU, audio: &mut AudioDevice<SquareWave>) {
-        if cpu.st > 0 { audio.resume(); }
-        else { audio.pause(); }
+    /// Turns on/off the buzzer based on the value of the sound timer, which is silenced while the CPU is faulted.
+    /// The XO-CHIP audio pattern buffer and pitch are passed to the buzzer, to play instead of the tone.
+    fn play_tone(&mut self, cpu: &CPU, audio: &mut AudioDevice<Buzzer>) {
+        {
+            let mut buzzer = audio.lock();
+            buzzer.synth.update(cpu);
+            if self.fault.is_some() { buzzer.synth.set_gate(false); }
+        }
+        // The device is paused while the debugger prompt is open
+        audio.resume();
     }
 }
 
-/// A representation of a square wave.
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32
+/// Executes instructions for the debugger prompt through the frontend, so that frames, timers, movies and captures
+/// advance just as they do when running normally.
+struct DebugTarget<'a> {
+    frontend: &'a mut WindowsSDL2,
+    canvas: &'a mut Canvas<Window>,
+    audio: &'a mut AudioDevice<Buzzer>,
 }
 
-impl AudioCallback for SquareWave {
+impl Target for DebugTarget<'_> {
+    fn step(&mut self, cpu: &mut CPU) -> Result<bool, CpuError> {
+        // Frames are finished and started as the instructions stepped use them up
+        while !self.frontend.may_run(cpu) {
+            self.frontend.end_frame(cpu);
+            self.frontend.begin_frame(cpu, self.audio);
+            // The buzzer stays quiet while the prompt is open
+            self.audio.pause();
+        }
+        let result = self.frontend.step(cpu);
+        if let Err(error) = result { self.frontend.set_fault(error, self.canvas); }
+        result
+    }
+}
+
+/// Converts a palette colour to an SDL colour.
+fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::RGB(r, g, b)
+}
+
+/// A keymap resolved to SDL keycodes and scancodes.
+struct KeyBindings {
+    keys: HashMap<Keycode, u8>,
+    scancode_keys: HashMap<Scancode, u8>,
+    hotkeys: HashMap<Keycode, Hotkey>,
+    scancode_hotkeys: HashMap<Scancode, Hotkey>,
+}
+
+impl KeyBindings {
+    /// Looks up the SDL key for every binding in the keymap.
+    fn resolve(keymap: &Keymap) -> Result<KeyBindings, String> {
+        let mut bindings = KeyBindings { keys: HashMap::new(), scancode_keys: HashMap::new(), hotkeys: HashMap::new(), scancode_hotkeys: HashMap::new() };
+        for (binding, key) in keymap.keys() {
+            match binding {
+                Binding::Key(name) => { bindings.keys.insert(resolve_keycode(name)?, *key); },
+                Binding::Scancode(name) => { bindings.scancode_keys.insert(resolve_scancode(name)?, *key); },
+            }
+        }
+        for (binding, hotkey) in keymap.hotkeys() {
+            match binding {
+                Binding::Key(name) => { bindings.hotkeys.insert(resolve_keycode(name)?, *hotkey); },
+                Binding::Scancode(name) => { bindings.scancode_hotkeys.insert(resolve_scancode(name)?, *hotkey); },
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// The CHIP-8 key bound to a host key, if any.
+    fn key(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<u8> {
+        keycode.and_then(|keycode| self.keys.get(&keycode))
+            .or_else(|| scancode.and_then(|scancode| self.scancode_keys.get(&scancode)))
+            .copied()
+    }
+
+    /// The hotkey bound to a host key, if any. Hotkeys take priority over CHIP-8 keys.
+    fn hotkey(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<Hotkey> {
+        keycode.and_then(|keycode| self.hotkeys.get(&keycode))
+            .or_else(|| scancode.and_then(|scancode| self.scancode_hotkeys.get(&scancode)))
+            .copied()
+    }
+}
+
+/// Looks up a key by the name SDL gives it.
+fn resolve_keycode(name: &str) -> Result<Keycode, String> {
+    Keycode::from_name(name).ok_or(format!("Unknown key name '{}'!", name))
+}
+
+/// Looks up a physical key position by the name SDL gives it.
+fn resolve_scancode(name: &str) -> Result<Scancode, String> {
+    Scancode::from_name(name).ok_or(format!("Unknown scancode name '{}'!", name))
+}
+
+/// Maps the number row to save slot numbers.
+fn slot_number(keycode: Keycode) -> Option<usize> {
+    let slot = match keycode {
+        Keycode::Num0 => 0,
+        Keycode::Num1 => 1,
+        Keycode::Num2 => 2,
+        Keycode::Num3 => 3,
+        Keycode::Num4 => 4,
+        Keycode::Num5 => 5,
+        Keycode::Num6 => 6,
+        Keycode::Num7 => 7,
+        Keycode::Num8 => 8,
+        Keycode::Num9 => 9,
+        _ => return None,
+    };
+    Some(slot)
+}
+
+impl Default for WindowsSDL2 {
+    fn default() -> Self { Self::new() }
+}
+
+/// The buzzer. Plays the tone, or the XO-CHIP audio pattern buffer if the program has loaded one.
+struct Buzzer {
+    synth: Synth,
+}
+
+impl AudioCallback for Buzzer {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        // Generates a square wave.
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
+        self.synth.fill(out);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,195 @@
+use std::io::{self, Write};
+use crate::chip8::{CPU, CpuError, StepOutcome, fnv1a};
+use crate::audio::WavRecorder;
+use crate::capture::Capture;
+use crate::movie::{Frame, Movie};
+use crate::palette::Palette;
+use crate::scheduler::{Scheduler, Timing, TIMER_HZ};
+
+/// A windowless runner, for batch execution and automated testing.
+/// Drives the CPU with the same frame cadence as the SDL2 frontend, but as fast as possible.
+pub struct HeadlessRunner{
+    scheduler: Scheduler, // Decides which instructions run in each frame
+    frames: u64, // Frames run so far, i.e., the number of 60Hz timer ticks
+    cycles: u64, // Instructions executed so far
+    recording: Option<Movie>, // If set, every frame run is appended to this movie
+    capture: Option<(Capture, Palette)>, // If set, the screen at the end of every frame is captured in this palette
+    wav: Option<WavRecorder>, // If set, the buzzer is rendered for every frame
+}
+
+/// Format used when writing the screen contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenFormat {
+    /// Plain text, one character per pixel. `.` is unlit, `#` is plane 1, `+` is plane 2 and `%` is both planes.
+    Ascii,
+    /// A plain (P1) portable bitmap. A pixel is black if lit on any plane.
+    Pbm,
+}
+
+impl ScreenFormat {
+    /// Looks up a format by name (`ascii` or `pbm`).
+    pub fn from_name(name: &str) -> Option<ScreenFormat> {
+        match name.to_lowercase().as_str() {
+            "ascii" | "txt" => Some(ScreenFormat::Ascii),
+            "pbm" => Some(ScreenFormat::Pbm),
+            _ => None,
+        }
+    }
+}
+
+impl HeadlessRunner{
+    /// Creates a new headless runner, executing `cycles_per_frame` instructions (at least 1) between each timer tick.
+    pub fn new(cycles_per_frame: u8) -> Self { Self::with_speed(cycles_per_frame.max(1) as u32 * TIMER_HZ) }
+
+    /// Creates a new headless runner, executing `instructions_per_second` for every 60 timer ticks. As in the SDL2
+    /// frontend, frames run different numbers of instructions if this isn't a multiple of 60.
+    pub fn with_speed(instructions_per_second: u32) -> Self {
+        HeadlessRunner {
+            scheduler: Scheduler::new(instructions_per_second),
+            frames: 0, cycles: 0, recording: None, capture: None, wav: None,
+        }
+    }
+
+    /// Sets how long each instruction takes, which defaults to [`Timing::Uniform`].
+    pub fn set_timing(&mut self, timing: Timing) { self.scheduler.set_timing(timing); }
+
+    /// Number of frames (60Hz timer ticks) run so far.
+    pub fn frames(&self) -> u64 { self.frames }
+
+    /// Number of instructions executed so far.
+    pub fn cycles(&self) -> u64 { self.cycles }
+
+    /// Starts recording the frames run into a movie, which should have been created with the CPU's seed and quirks.
+    pub fn start_recording(&mut self, movie: Movie) { self.recording = Some(movie); }
+
+    /// Stops recording, returning the movie recorded.
+    pub fn take_recording(&mut self) -> Option<Movie> { self.recording.take() }
+
+    /// Starts capturing the screen at the end of every frame run, in the given palette.
+    pub fn start_capture(&mut self, capture: Capture, palette: Palette) { self.capture = Some((capture, palette)); }
+
+    /// Stops capturing, returning the capture to be finished. The screen at the end of the last frame run is captured first.
+    pub fn take_capture(&mut self, cpu: &CPU) -> Option<Capture> {
+        self.capture_frame(cpu);
+        self.capture.take().map(|(capture, _)| capture)
+    }
+
+    /// Runs the CPU for up to `cycles` instructions, stopping early if the program exits.
+    /// As in the SDL2 frontend, the timers are ticked at the start of every frame of instructions.
+    /// Returns true if the program exited.
+    pub fn run(&mut self, cpu: &mut CPU, cycles: u64) -> Result<bool, CpuError> {
+        let target = self.cycles + cycles;
+        while self.cycles < target {
+            // At under 60 instructions per second, some frames run no instructions at all
+            while !self.scheduler.may_run(cpu) {
+                self.start_frame(cpu);
+                self.scheduler.begin_frame();
+            }
+            if self.step(cpu)? { return Ok(true); }
+        }
+        Ok(cpu.has_exited())
+    }
+
+    /// Replays a movie, setting the keys and running the instructions recorded for each frame.
+    /// The CPU should have been set up with [`Movie::create_cpu`]. Returns true if the program exited.
+    pub fn play(&mut self, cpu: &mut CPU, movie: &Movie) -> Result<bool, CpuError> {
+        for frame in movie.frames() {
+            cpu.set_key_mask(frame.keys);
+            self.start_frame(cpu);
+            for _cycle in 0..frame.cycles {
+                if self.step(cpu)? { return Ok(true); }
+            }
+        }
+        Ok(cpu.has_exited())
+    }
+
+    /// Starts rendering the buzzer to a WAV file, one 60th of a second for every frame run.
+    pub fn start_audio(&mut self, wav: WavRecorder) { self.wav = Some(wav); }
+
+    /// Stops rendering the buzzer, returning the WAV file to be finished.
+    pub fn take_audio(&mut self) -> Option<WavRecorder> { self.wav.take() }
+
+    /// Ticks the timers at the start of a frame, recording the frame's input if a recording is in progress.
+    /// The screen at the end of the previous frame is captured first. As in the SDL2 frontend, the frame's audio is
+    /// rendered once the timers have been ticked.
+    fn start_frame(&mut self, cpu: &mut CPU) {
+        self.capture_frame(cpu);
+        cpu.tick();
+        self.frames += 1;
+        if let Some(wav) = self.wav.as_mut() { wav.push_frame(cpu); }
+        if let Some(movie) = self.recording.as_mut() {
+            movie.push_frame(Frame { keys: cpu.key_mask(), cycles: 0 });
+        }
+    }
+
+    /// Captures the screen at the end of the current frame, if capturing and a frame has been run.
+    fn capture_frame(&mut self, cpu: &CPU) {
+        if let (Some((capture, palette)), true) = (self.capture.as_mut(), self.frames > 0) {
+            capture.push_frame(cpu, palette);
+        }
+    }
+
+    /// Executes one instruction. Returns true if the program has exited.
+    fn step(&mut self, cpu: &mut CPU) -> Result<bool, CpuError> {
+        match cpu.step()? {
+            outcome @ (StepOutcome::Executed | StepOutcome::DisplayWait) => {
+                self.cycles += 1;
+                if let Some(movie) = self.recording.as_mut() { movie.count_cycle(); }
+                if outcome == StepOutcome::DisplayWait { self.scheduler.end_frame(); }
+                Ok(false)
+            },
+            StepOutcome::Exited => Ok(true),
+        }
+    }
+}
+
+/// Writes the final report for a run: the screen contents in the given format, plus the registers, timers, stack,
+/// a hash of memory and the number of frames and instructions run.
+/// For PBM output, the state is written as comments in the header so the result is still a valid image.
+pub fn write_report<W: Write>(cpu: &CPU, runner: &HeadlessRunner, format: ScreenFormat, out: &mut W) -> io::Result<()> {
+    let mut state = state_lines(cpu);
+    state.push(format!("frames: {} cycles: {}", runner.frames(), runner.cycles()));
+
+    match format {
+        ScreenFormat::Ascii => {
+            write_screen(cpu, format, out)?;
+            for line in state { writeln!(out, "{}", line)?; }
+        },
+        ScreenFormat::Pbm => {
+            writeln!(out, "P1")?;
+            for line in state { writeln!(out, "# {}", line)?; }
+            writeln!(out, "{} {}", cpu.screen_width(), cpu.screen_height())?;
+            write_screen(cpu, format, out)?;
+        },
+    }
+    Ok(())
+}
+
+/// Writes the pixels of the screen, one row per line.
+fn write_screen<W: Write>(cpu: &CPU, format: ScreenFormat, out: &mut W) -> io::Result<()> {
+    let (width, height) = (cpu.screen_width(), cpu.screen_height());
+    for row in cpu.screen.iter().take(height) {
+        let line: String = row.iter().take(width).map(|pixel| match (format, pixel & 0b11) {
+            (ScreenFormat::Pbm, 0) => '0',
+            (ScreenFormat::Pbm, _) => '1',
+            (ScreenFormat::Ascii, 0) => '.',
+            (ScreenFormat::Ascii, 1) => '#',
+            (ScreenFormat::Ascii, 2) => '+',
+            (ScreenFormat::Ascii, _) => '%',
+        }).collect();
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Describes the registers, timers, stack and a hash of memory as lines of plain text.
+pub(crate) fn state_lines(cpu: &CPU) -> Vec<String> {
+    let registers: Vec<String> = cpu.registers().iter().enumerate().map(|(index, value)| format!("V{:X}={:02X}", index, value)).collect();
+    let stack: Vec<String> = cpu.stack()[..cpu.sp() as usize].iter().map(|addr| format!("0x{:04X}", addr)).collect();
+    vec![
+        format!("PC=0x{:04X} I=0x{:04X} SP={} DT={} ST={}", cpu.pc(), cpu.i(), cpu.sp(), cpu.delay_timer(), cpu.sound_timer()),
+        registers.join(" "),
+        format!("stack: [{}]", stack.join(", ")),
+        format!("memory hash: {:016x}", fnv1a(cpu.memory())),
+    ]
+}
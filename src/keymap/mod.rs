@@ -0,0 +1,249 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A key on the host keyboard, named as SDL names it (e.g. `Q`, `Keypad 7`, `Backspace`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Binding {
+    /// A key identified by the character it produces, so it moves with the keyboard layout.
+    Key(String),
+    /// A key identified by its physical position, regardless of layout. Written `scancode:<name>`, where the name is
+    /// that of the key in that position on a US QWERTY keyboard.
+    Scancode(String),
+}
+
+impl Binding {
+    /// Parses a binding: a key name, or `scancode:` followed by a key name.
+    pub fn parse(text: &str) -> Binding {
+        match text.strip_prefix("scancode:") {
+            Some(name) => Binding::Scancode(name.to_string()),
+            None => Binding::Key(text.to_string()),
+        }
+    }
+
+    /// Returns true if both bindings name the same key. Key names are compared ignoring case, as SDL does.
+    pub fn same_key(&self, other: &Binding) -> bool {
+        match (self, other) {
+            (Binding::Key(name), Binding::Key(other)) | (Binding::Scancode(name), Binding::Scancode(other)) =>
+                name.eq_ignore_ascii_case(other),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(name) => write!(f, "{}", name),
+            Binding::Scancode(name) => write!(f, "scancode:{}", name),
+        }
+    }
+}
+
+/// Actions of the frontend itself, rather than of the CHIP-8 keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Hotkey {
+    /// Close the interpreter.
+    Quit,
+    /// Run one more instruction per frame.
+    SpeedUp,
+    /// Run one fewer instruction per frame.
+    SpeedDown,
+    /// Save the state to the selected slot.
+    SaveState,
+    /// Load the state from the selected slot.
+    LoadState,
+    /// Play the game backwards while held.
+    Rewind,
+    /// Break into the debugger, if attached.
+    DebugBreak,
+    /// Switch to the next built-in palette.
+    CyclePalette,
+    /// Switch the display filter on or off.
+    ToggleFilter,
+    /// Switch between a window and fullscreen.
+    ToggleFullscreen,
+    /// Write the screen to a PNG file.
+    Screenshot,
+    /// Start or stop capturing gameplay to an animated GIF.
+    ToggleCapture,
+}
+
+impl Hotkey {
+    /// Every hotkey, with the name used for it in keymap files.
+    pub const ALL: [(Hotkey, &'static str); 12] = [
+        (Hotkey::Quit, "quit"),
+        (Hotkey::SpeedUp, "speed_up"),
+        (Hotkey::SpeedDown, "speed_down"),
+        (Hotkey::SaveState, "save_state"),
+        (Hotkey::LoadState, "load_state"),
+        (Hotkey::Rewind, "rewind"),
+        (Hotkey::DebugBreak, "debug_break"),
+        (Hotkey::CyclePalette, "cycle_palette"),
+        (Hotkey::ToggleFilter, "toggle_filter"),
+        (Hotkey::ToggleFullscreen, "toggle_fullscreen"),
+        (Hotkey::Screenshot, "screenshot"),
+        (Hotkey::ToggleCapture, "toggle_capture"),
+    ];
+
+    /// Looks up a hotkey by its name in keymap files.
+    pub fn from_name(name: &str) -> Option<Hotkey> {
+        Self::ALL.iter().find(|(_, hotkey_name)| *hotkey_name == name).map(|(hotkey, _)| *hotkey)
+    }
+
+    /// The hotkey's name in keymap files.
+    pub fn name(&self) -> &'static str {
+        Self::ALL.iter().find(|(hotkey, _)| hotkey == self).map(|(_, name)| *name).unwrap()
+    }
+}
+
+/// The names of the built-in keymap presets.
+pub const PRESETS: [&str; 5] = ["qwerty", "azerty", "qwertz", "dvorak", "numpad"];
+
+/// Host keys for CHIP-8 keys 0-F, in the 4x4 grid order of the original keypad (1 2 3 C / 4 5 6 D / 7 8 9 E / A 0 B F).
+const GRID_ORDER: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+/// Maps host keys to CHIP-8 keys 0-F and to the frontend's hotkeys.
+///
+/// Keymap files are TOML. They start from a preset (`qwerty` unless `preset` says otherwise), then rebind the keys
+/// listed in the `[keys]` and `[hotkeys]` tables, replacing all of the preset's bindings for each one listed.
+/// A key can be given a single binding or a list of them, but no host key can be bound to two different actions:
+///
+/// ```toml
+/// preset = "azerty"
+///
+/// [keys]
+/// 5 = "Up"
+/// 8 = ["Down", "scancode:S"]
+///
+/// [hotkeys]
+/// quit = "F8"
+/// speed_up = ["=", "Keypad +"]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap{
+    keys: Vec<(Binding, u8)>, // Host key and the CHIP-8 key it presses
+    hotkeys: Vec<(Binding, Hotkey)>,
+}
+
+impl Keymap{
+    /// Looks up a built-in preset by name: `qwerty`, `azerty`, `qwertz`, `dvorak` or `numpad`.
+    /// The letter presets all map the 4x4 block at the top-left of the keyboard, and `numpad` maps the numeric keypad.
+    pub fn preset(name: &str) -> Option<Keymap> {
+        let grid: [&str; 16] = match name.to_lowercase().as_str() {
+            "qwerty" => ["1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V"],
+            // The AZERTY number row produces symbols unless Shift is held, so it is bound by position instead
+            "azerty" => ["scancode:1", "scancode:2", "scancode:3", "scancode:4", "A", "Z", "E", "R", "Q", "S", "D", "F", "W", "X", "C", "V"],
+            "qwertz" => ["1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Y", "X", "C", "V"],
+            "dvorak" => ["1", "2", "3", "4", "'", ",", ".", "P", "A", "O", "E", "U", ";", "Q", "J", "K"],
+            "numpad" => ["Keypad 7", "Keypad 8", "Keypad 9", "Keypad /", "Keypad 4", "Keypad 5", "Keypad 6", "Keypad *",
+                "Keypad 1", "Keypad 2", "Keypad 3", "Keypad -", "Keypad 0", "Keypad .", "Keypad Enter", "Keypad +"],
+            _ => return None,
+        };
+
+        let keys = grid.iter().zip(GRID_ORDER).map(|(name, key)| (Binding::parse(name), key)).collect();
+        let hotkeys = [
+            ("Escape", Hotkey::Quit),
+            ("=", Hotkey::SpeedUp),
+            ("+", Hotkey::SpeedUp),
+            ("-", Hotkey::SpeedDown),
+            ("O", Hotkey::SaveState),
+            ("P", Hotkey::LoadState),
+            ("Backspace", Hotkey::Rewind),
+            ("Pause", Hotkey::DebugBreak),
+            ("F10", Hotkey::DebugBreak),
+            ("Tab", Hotkey::CyclePalette),
+            ("F3", Hotkey::ToggleFilter),
+            ("F11", Hotkey::ToggleFullscreen),
+            ("F12", Hotkey::Screenshot),
+            ("F9", Hotkey::ToggleCapture),
+        ].iter().map(|(name, hotkey)| (Binding::parse(name), *hotkey)).collect();
+        Some(Keymap { keys, hotkeys })
+    }
+
+    /// Loads a keymap by preset name, or from a TOML file if `name` isn't a preset.
+    pub fn load(name: &str) -> Result<Keymap, String> {
+        if let Some(keymap) = Self::preset(name) { return Ok(keymap); }
+        let text = fs::read_to_string(Path::new(name))
+            .map_err(|error| format!("'{}' is not a keymap preset ({}), and can't be read as a file: {}", name, PRESETS.join(", "), error))?;
+        Self::from_toml(&text).map_err(|error| format!("{}: {}", name, error))
+    }
+
+    /// Parses a keymap file.
+    pub fn from_toml(text: &str) -> Result<Keymap, String> {
+        let table: toml::Table = text.parse().map_err(|error: toml::de::Error| error.to_string().trim_end().to_string())?;
+
+        let mut keymap = match table.get("preset") {
+            None => Self::preset("qwerty").unwrap(),
+            Some(toml::Value::String(name)) => Self::preset(name)
+                .ok_or(format!("unknown preset '{}'; expected one of: {}", name, PRESETS.join(", ")))?,
+            Some(_) => return Err("'preset' must be a string".to_string()),
+        };
+
+        for (name, value) in &table {
+            match (name.as_str(), value) {
+                ("preset", _) => { },
+                ("keys", toml::Value::Table(keys)) => for (key_name, bindings) in keys {
+                    let key = u8::from_str_radix(key_name, 16).ok().filter(|key| *key < 16)
+                        .ok_or(format!("[keys]: '{}' is not a CHIP-8 key (0-F)", key_name))?;
+                    let bindings = parse_bindings(bindings).map_err(|error| format!("[keys] {}: {}", key_name, error))?;
+                    keymap.keys.retain(|(_, bound)| *bound != key);
+                    keymap.keys.extend(bindings.into_iter().map(|binding| (binding, key)));
+                },
+                ("hotkeys", toml::Value::Table(hotkeys)) => for (hotkey_name, bindings) in hotkeys {
+                    let names: Vec<&str> = Hotkey::ALL.iter().map(|(_, name)| *name).collect();
+                    let hotkey = Hotkey::from_name(hotkey_name)
+                        .ok_or(format!("[hotkeys]: unknown hotkey '{}'; expected one of: {}", hotkey_name, names.join(", ")))?;
+                    let bindings = parse_bindings(bindings).map_err(|error| format!("[hotkeys] {}: {}", hotkey_name, error))?;
+                    keymap.hotkeys.retain(|(_, bound)| *bound != hotkey);
+                    keymap.hotkeys.extend(bindings.into_iter().map(|binding| (binding, hotkey)));
+                },
+                ("keys" | "hotkeys", _) => return Err(format!("'{}' must be a table", name)),
+                _ => return Err(format!("unknown setting '{}'", name)),
+            }
+        }
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    /// Checks that no host key is bound to two different actions, as only one of them would work.
+    fn check_conflicts(&self) -> Result<(), String> {
+        let actions: Vec<(&Binding, String)> = self.keys.iter().map(|(binding, key)| (binding, format!("CHIP-8 key {:X}", key)))
+            .chain(self.hotkeys.iter().map(|(binding, hotkey)| (binding, format!("hotkey {}", hotkey.name()))))
+            .collect();
+        for (index, (binding, action)) in actions.iter().enumerate() {
+            let earlier = actions[..index].iter().find(|(other, other_action)| other.same_key(binding) && other_action != action);
+            if let Some((_, other_action)) = earlier {
+                return Err(format!("'{}' is bound to both {} and {}", binding, other_action, action));
+            }
+        }
+        Ok(())
+    }
+
+    /// The host keys bound to CHIP-8 keys, with the CHIP-8 key each one presses.
+    pub fn keys(&self) -> &[(Binding, u8)] {
+        &self.keys
+    }
+
+    /// The host keys bound to hotkeys.
+    pub fn hotkeys(&self) -> &[(Binding, Hotkey)] {
+        &self.hotkeys
+    }
+}
+
+impl Default for Keymap {
+    /// Defaults to the QWERTY preset.
+    fn default() -> Self { Keymap::preset("qwerty").unwrap() }
+}
+
+/// Parses a binding, or a list of bindings.
+fn parse_bindings(value: &toml::Value) -> Result<Vec<Binding>, String> {
+    match value {
+        toml::Value::String(name) => Ok(vec![Binding::parse(name)]),
+        toml::Value::Array(names) => names.iter().map(|name| match name {
+            toml::Value::String(name) => Ok(Binding::parse(name)),
+            _ => Err("key names must be strings".to_string()),
+        }).collect(),
+        _ => Err("expected a key name or a list of key names".to_string()),
+    }
+}
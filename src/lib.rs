@@ -0,0 +1,46 @@
+//! A CHIP-8 interpreter, supporting the SUPER-CHIP 1.1 and XO-CHIP extensions.
+//!
+//! The emulator core is [`chip8::CPU`], which has no dependencies on any windowing or audio library, and so
+//! can be embedded in other tools. A CPU is driven by calling [`chip8::CPU::step`] to execute instructions
+//! and [`chip8::CPU::tick`] at 60Hz to update the timers, while reporting key presses with
+//! [`chip8::CPU::update_key`] and reading the display from [`chip8::CPU::screen`].
+//!
+//! ```
+//! use chip8_interpreter::chip8::{CPU, Quirks, StepOutcome};
+//!
+//! let mut cpu = CPU::new(Quirks::VIP);
+//! cpu.load(&[0x60, 0x2A, 0x00, 0xFD]).unwrap(); // V0 := 0x2A, then exit
+//! while cpu.step().unwrap() != StepOutcome::Exited { }
+//! assert_eq!(cpu.registers()[0], 0x2A);
+//! ```
+//!
+//! [`headless::HeadlessRunner`] drives a CPU without a window, for batch execution and automated testing, and
+//! [`debugger::Debugger`] provides breakpoints, stepping and inspection from a terminal prompt.
+//! [`disassembler::disassemble`] turns a ROM into Octo- or Cowgod-style mnemonics, using the shared
+//! [`chip8::Instruction`] decoder, and [`assembler::assemble`] turns the Cowgod-style listing back into a ROM.
+//!
+//! Optional frontends are enabled with cargo features:
+//! * `sdl` (default): [`graphics::WindowsSDL2`], a window, keyboard and audio frontend using SDL2.
+//! * `file-dialog` (default): a file picker for choosing a ROM when none is given on the command line.
+
+pub mod assembler;
+pub mod audio;
+pub mod capture;
+pub mod chip8;
+pub mod debugger;
+pub mod disassembler;
+pub mod filter;
+pub mod headless;
+pub mod keymap;
+pub mod movie;
+pub mod palette;
+pub mod rewind;
+pub mod savestate;
+pub mod scheduler;
+pub mod screenshot;
+pub mod viewport;
+#[cfg(feature = "sdl")]
+pub mod graphics;
+
+/// Re-exported, as keys and register indices are passed to the CPU as 4-bit values.
+pub use bobbin_bits::U4;
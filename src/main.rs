@@ -1,11 +1,225 @@
-mod chip8;
-mod graphics;
+mod options;
 
-use std::{fs, env};
+use std::{fs, env, io::{self, Write}, path::{Path, PathBuf}, process};
+#[cfg(feature = "ctrl-c")]
+use std::sync::atomic::Ordering;
+#[cfg(feature = "file-dialog")]
 use rfd::FileDialog;
-use crate::{chip8::CPU, graphics::WindowsSDL2};
+use chip8_interpreter::chip8::{CPU, RandomKind, RandomSource};
+use chip8_interpreter::headless::{self, HeadlessRunner};
+use chip8_interpreter::debugger::Debugger;
+use chip8_interpreter::audio::WavRecorder;
+use chip8_interpreter::capture::Capture;
+use chip8_interpreter::movie::Movie;
+use chip8_interpreter::screenshot::Screenshots;
+use chip8_interpreter::{assembler, disassembler};
+#[cfg(feature = "sdl")]
+use chip8_interpreter::graphics::WindowsSDL2;
+#[cfg(feature = "sdl")]
+use chip8_interpreter::filter::DisplayFilter;
+#[cfg(feature = "sdl")]
+use chip8_interpreter::savestate::SaveSlots;
+use crate::options::{Mode, Options};
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(msg) => { println!("{}", msg); process::exit(2); },
+    };
+
+    // The banner is skipped in headless mode and by subcommands, where stdout may be carrying their output
+    if !options.headless && options.mode == Mode::Run { print_banner(); }
+
+    let rom_path: String;
+
+    if let Some(path) = options.rom_path.clone() {
+        rom_path = path;
+    } else if options.headless || options.mode != Mode::Run {
+        println!("No game given! Headless mode and subcommands require a ROM (or source) path on the command line.");
+        process::exit(2);
+    } else {
+        match pick_rom(){
+            Some(path) => { rom_path = path; },
+            None => { println!("No game selected! Exiting..."); return; },
+        }
+    }
+    
+    if options.mode == Mode::Assemble {
+        process::exit(run_assembler(&rom_path, &options));
+    }
+
+    let rom = fs::read(&rom_path).expect("ROM not readable! Exiting...");
+
+    // A replayed movie brings its own quirks and random number generator
+    let replay = options.replay.as_ref().map(|path| match Movie::load(Path::new(path)) {
+        Ok(movie) => movie,
+        Err(error) => { println!("Unable to read movie {}: {}! Exiting...", path, error); process::exit(1); },
+    });
+    let quirks = replay.as_ref().map(Movie::quirks).unwrap_or(options.quirks);
+    let random = match &replay {
+        Some(movie) => movie.random().clone(),
+        None => create_random_source(&options).unwrap_or_else(|error| { println!("{}! Exiting...", error); process::exit(1); }),
+    };
+
+    let mut emu = CPU::new(quirks);
+    emu.set_random_source(random.clone());
+    if let Some(movie) = &replay {
+        if let Err(error) = movie.check_rom(&rom) {
+            println!("Unable to replay movie: {}! Exiting...", error);
+            process::exit(1);
+        }
+    }
+    if let Err(error) = emu.load(&rom) {
+        println!("Unable to load ROM: {}! Exiting...", error);
+        process::exit(1);
+    }
+    let recording = options.record.as_ref().map(|_| Movie::new(&rom, &random, quirks));
+
+    if options.mode == Mode::Disassemble {
+        process::exit(run_disassembler(&emu, rom.len(), &options));
+    }
+
+    if options.headless && options.debug {
+        // The debugger runs at the same speed and timing as a headless run, and records to the same movie
+        let mut runner = HeadlessRunner::with_speed(options.speed());
+        runner.set_timing(options.timing);
+        if let Some(movie) = recording { runner.start_recording(movie); }
+        let mut debugger = create_debugger();
+        if let Err(error) = debugger.run(&mut runner, &mut emu, &mut io::stdin().lock(), &mut io::stdout()) {
+            println!("Debugger error: {}", error);
+        }
+        save_recording(runner.take_recording(), &options);
+        return;
+    }
+
+    if options.headless {
+        process::exit(run_headless(&mut emu, &rom_path, replay, recording, &options));
+    }
+
+    run_interpreter(&mut emu, &rom, &rom_path, replay, recording, &options);
+}
+
+/// Creates the random number generator chosen with `--rng`, seeded with `--seed` (or a random seed).
+fn create_random_source(options: &Options) -> Result<RandomSource, String> {
+    let seed = options.seed.unwrap_or_else(rand::random);
+    match options.rng {
+        RandomKind::Vip => {
+            let path = options.vip_interpreter.as_ref()
+                .ok_or("The VIP random number generator needs an image of the COSMAC VIP's CHIP-8 interpreter, given with --vip-interpreter <file>")?;
+            let interpreter = fs::read(path).map_err(|error| format!("Unable to read {}: {}", path, error))?;
+            RandomSource::vip(seed, &interpreter).map_err(|error| format!("Unable to use {}: {}", path, error))
+        },
+        RandomKind::Seeded | RandomKind::Scripted => Ok(RandomSource::seeded(seed)),
+    }
+}
+
+/// Creates a debugger, which Ctrl+C interrupts rather than stopping the interpreter.
+#[cfg(feature = "ctrl-c")]
+fn create_debugger() -> Debugger {
+    let debugger = Debugger::new();
+    let interrupt = debugger.interrupt_handle();
+    if let Err(error) = ctrlc::set_handler(move || interrupt.store(true, Ordering::SeqCst)) {
+        println!("Unable to handle Ctrl+C ({}): use breakpoints to pause execution.", error);
+    }
+    debugger
+}
+
+/// Creates a debugger. Without the `ctrl-c` feature, only breakpoints pause execution.
+#[cfg(not(feature = "ctrl-c"))]
+fn create_debugger() -> Debugger {
+    Debugger::new()
+}
+
+/// Writes a recorded movie to the `--record` file. Returns false if it couldn't be written.
+fn save_recording(movie: Option<Movie>, options: &Options) -> bool {
+    let (movie, path) = match (movie, &options.record) { (Some(movie), Some(path)) => (movie, path), _ => return true };
+    match movie.save(Path::new(path)) {
+        Ok(()) => { eprintln!("Recorded {} frames to {}", movie.frames().len(), path); true },
+        Err(error) => { eprintln!("Unable to write movie {}: {}", path, error); false },
+    }
+}
+
+/// Starts capturing gameplay to the `--capture` file, if given.
+fn create_capture(options: &Options) -> Result<Option<Capture>, String> {
+    let Some(path) = &options.capture else { return Ok(None) };
+    Capture::create(Path::new(path), options.capture_scale, &options.palette)
+        .map(Some)
+        .map_err(|error| format!("Unable to capture to {}: {}", path, error))
+}
+
+/// Finishes a capture of gameplay. Returns false if it couldn't be written.
+fn finish_capture(capture: Option<Capture>) -> bool {
+    match capture.map(Capture::finish) {
+        Some(Ok(frames)) => { eprintln!("Captured {} frames", frames); true },
+        Some(Err(error)) => { eprintln!("Unable to write capture: {}", error); false },
+        None => true,
+    }
+}
+
+/// Starts rendering the buzzer to the `--wav` file, if given.
+fn create_wav(options: &Options) -> Result<Option<WavRecorder>, String> {
+    let Some(path) = &options.wav else { return Ok(None) };
+    WavRecorder::create(Path::new(path), options.tone.clone())
+        .map(Some)
+        .map_err(|error| format!("Unable to write {}: {}", path, error))
+}
+
+/// Finishes rendering the buzzer to a WAV file. Returns false if it couldn't be written.
+fn finish_wav(wav: Option<WavRecorder>) -> bool {
+    match wav.map(WavRecorder::finish) {
+        Some(Ok(frames)) => { eprintln!("Rendered {} frames of audio", frames); true },
+        Some(Err(error)) => { eprintln!("Unable to write audio: {}", error); false },
+        None => true,
+    }
+}
+
+/// Disassembles the loaded ROM, or the memory range given by `--start` and `--length`, to stdout or the output file.
+/// Returns the process exit code.
+fn run_disassembler(emu: &CPU, rom_length: usize, options: &Options) -> i32 {
+    let start = options.start.unwrap_or(0x200) as usize;
+    let length = match (options.start, options.length) {
+        (_, Some(length)) => length as usize,
+        (None, None) => rom_length,
+        (Some(_), None) => 0x200 + rom_length - start.min(0x200 + rom_length),
+    };
+    let end = (start + length).min(emu.memory().len());
+    let listing = disassembler::disassemble(&emu.memory()[start.min(end)..end], start as u16, options.syntax);
+
+    let written = match &options.output {
+        Some(path) => fs::write(path, listing),
+        None => io::stdout().write_all(listing.as_bytes()),
+    };
+    match written {
+        Ok(()) => 0,
+        Err(error) => { println!("Unable to write output: {}", error); 1 },
+    }
+}
+
+/// Assembles the source file to the output file, or to the source path with a `.ch8` extension.
+/// Returns the process exit code.
+fn run_assembler(source_path: &str, options: &Options) -> i32 {
+    let rom = match assembler::assemble_file(Path::new(source_path)) {
+        Ok(rom) => rom,
+        Err(errors) => {
+            for error in &errors { eprintln!("{}", error); }
+            eprintln!("{} error(s); no ROM written.", errors.len());
+            return 1;
+        },
+    };
+
+    let output = match &options.output {
+        Some(path) => PathBuf::from(path),
+        None => Path::new(source_path).with_extension("ch8"),
+    };
+    match fs::write(&output, &rom) {
+        Ok(()) => { println!("Wrote {} bytes to {}", rom.len(), output.display()); 0 },
+        Err(error) => { println!("Unable to write {}: {}", output.display(), error); 1 },
+    }
+}
+
+/// Prints the title banner.
+fn print_banner() {
     println!("      _     _       _____   _       _                           _            ");
     println!("     | |   (_)     |  _  | (_)     | |                         | |           ");
     println!("  ___| |__  _ _ __  \\ V /   _ _ __ | |_ ___ _ __ _ __  _ __ ___| |_ ___ _ __ ");
@@ -15,35 +229,130 @@ fn main() {
     println!("             | |                                | |                          ");
     println!("             |_|                                |_|                          ");
     println!("--                Written by Joshua Wardle (buildz), 2023                  --");
+}
 
-    let args: Vec<String> = env::args().collect();
-    
-    let rom_path: String;
-
-    if args.len() > 1 {
-        rom_path = args[1].to_string();
-    } else {
-        let files = FileDialog::new()
-        .add_filter("CHIP-8 ROM", &["ch8"])
-        .set_directory("/")
-        .pick_file();
+/// Runs the loaded ROM without a window, then writes the screen and CPU state to stdout or the output file.
+/// If a movie is given, it is replayed in full instead of running for `--cycles` instructions.
+/// With `--screenshot`, the final screen is also written to a PNG file.
+/// Returns the process exit code: 0 on success, 1 if the CPU faulted or the output couldn't be written.
+fn run_headless(emu: &mut CPU, rom_path: &str, replay: Option<Movie>, recording: Option<Movie>, options: &Options) -> i32 {
+    let mut runner = HeadlessRunner::with_speed(options.speed());
+    runner.set_timing(options.timing);
+    if let Some(movie) = recording { runner.start_recording(movie); }
+    match create_capture(options) {
+        Ok(Some(capture)) => runner.start_capture(capture, options.palette.clone()),
+        Ok(None) => { },
+        Err(msg) => { eprintln!("{}", msg); return 1; },
+    }
+    match create_wav(options) {
+        Ok(Some(wav)) => runner.start_audio(wav),
+        Ok(None) => { },
+        Err(msg) => { eprintln!("{}", msg); return 1; },
+    }
+    let result = match &replay {
+        Some(movie) => runner.play(emu, movie),
+        None => runner.run(emu, options.cycles),
+    };
+    if !save_recording(runner.take_recording(), options) { return 1; }
+    if !finish_capture(runner.take_capture(emu)) { return 1; }
+    if !finish_wav(runner.take_audio()) { return 1; }
 
-        match files{
-            Some(path) => { rom_path = path.into_os_string().into_string().unwrap(); },
-            None => { println!("No game selected! Exiting..."); return; },
+    if let Some(directory) = &options.screenshot {
+        let screenshots = Screenshots::new(Path::new(directory), Path::new(rom_path), options.screenshot_scale);
+        match screenshots.save(emu, &options.palette, runner.frames()) {
+            Ok(path) => eprintln!("Saved screenshot to {}", path.display()),
+            Err(error) => { eprintln!("Unable to save screenshot: {}", error); return 1; },
         }
     }
-    
-    let rom = fs::read(&rom_path).expect("ROM not readable! Exiting...");
 
-    let mut emu = CPU::new();
-    emu.load(&rom);
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => match fs::File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(error) => { println!("Unable to create {}: {}", path, error); return 1; },
+        },
+        None => Box::new(io::stdout()),
+    };
+
+    if let Err(error) = headless::write_report(emu, &runner, options.format, &mut out) {
+        println!("Unable to write output: {}", error);
+        return 1;
+    }
 
+    match result {
+        Ok(_) => 0,
+        Err(error) => { eprintln!("CPU fault: {}", error); 1 },
+    }
+}
+
+/// Shows a file picker so the user can choose a ROM.
+#[cfg(feature = "file-dialog")]
+fn pick_rom() -> Option<String> {
+    FileDialog::new()
+        .add_filter("CHIP-8 ROM", &["ch8"])
+        .set_directory("/")
+        .pick_file()
+        .map(|path| path.into_os_string().into_string().unwrap())
+}
+
+/// Without the `file-dialog` feature, a ROM must be given on the command line.
+#[cfg(not(feature = "file-dialog"))]
+fn pick_rom() -> Option<String> {
+    println!("Built without a file picker: pass the ROM path on the command line.");
+    None
+}
+
+/// Runs the loaded ROM in the SDL2 frontend.
+#[cfg(feature = "sdl")]
+fn run_interpreter(emu: &mut CPU, rom: &[u8], rom_path: &str, replay: Option<Movie>, recording: Option<Movie>, options: &Options) {
     let mut graphics_layer = WindowsSDL2::new();
+    graphics_layer.set_instructions_per_second(options.speed());
+    graphics_layer.set_timing(options.timing);
+    graphics_layer.set_rewind_speed(options.rewind_speed);
+    graphics_layer.set_window_scale(options.scale);
+    graphics_layer.set_scale_mode(options.scaling);
+    graphics_layer.set_palette(options.palette.clone());
+    // Screenshots go to the working directory unless another is given
+    let screenshot_directory = options.screenshot.as_deref().unwrap_or(".");
+    graphics_layer.set_screenshots(Screenshots::new(Path::new(screenshot_directory), Path::new(rom_path), options.screenshot_scale));
+    graphics_layer.set_filter(DisplayFilter::new(options.filter, options.decay));
+    if let Err(msg) = graphics_layer.set_keymap(&options.keymap) {
+        println!("Invalid keymap: {}", msg);
+        return;
+    }
+    // Save states are kept on disk, one file per ROM
+    match SaveSlots::in_user_data_dir(rom) {
+        Some(save_slots) => graphics_layer.set_save_slots(save_slots),
+        None => println!("No user data directory found: save states will only be kept until exit."),
+    }
+    if options.debug {
+        graphics_layer.attach_debugger(create_debugger());
+    }
+    if let Some(movie) = replay { graphics_layer.play_movie(movie); }
+    if let Some(movie) = recording { graphics_layer.record_movie(movie); }
+    graphics_layer.set_capture_scale(options.capture_scale);
+    match create_capture(options) {
+        Ok(Some(capture)) => graphics_layer.start_capture(capture),
+        Ok(None) => { },
+        Err(msg) => { println!("{}", msg); return; },
+    }
+    graphics_layer.set_tone(options.tone.clone());
+    match create_wav(options) {
+        Ok(Some(wav)) => graphics_layer.record_audio(wav),
+        Ok(None) => { },
+        Err(msg) => { println!("{}", msg); return; },
+    }
 
-    match graphics_layer.start_interpreter(&mut emu){
+    match graphics_layer.start_interpreter(emu){
         Ok(()) => {  }
         Err(msg) => println!("An error occurred: {}", msg),
     }
+    save_recording(graphics_layer.take_recording(), options);
+    finish_capture(graphics_layer.take_capture());
+    finish_wav(graphics_layer.take_audio());
+}
 
+/// Without the `sdl` feature, there is no window to run the ROM in.
+#[cfg(not(feature = "sdl"))]
+fn run_interpreter(_emu: &mut CPU, _rom: &[u8], _rom_path: &str, _replay: Option<Movie>, _recording: Option<Movie>, _options: &Options) {
+    println!("Built without the SDL2 frontend: rebuild with `--features sdl` to play games.");
 }
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::chip8::{CPU, Quirks, RandomSource, fnv1a};
+
+/// Version of the movie file format.
+pub const FORMAT_VERSION: u16 = 3;
+
+/// Identifies a movie file.
+const MAGIC: &[u8; 4] = b"C8MV";
+
+/// Size of the file header: magic, format version, ROM hash, RNG kind and state, quirks and frame count.
+const HEADER_SIZE: usize = 4 + 2 + 8 + 1 + 8 + 4 + 4;
+
+/// The input for one frame of a movie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The pressed keys, with bit n set if key n is pressed (see [`CPU::key_mask`]).
+    pub keys: u16,
+    /// The number of instructions executed in the frame.
+    pub cycles: u8,
+}
+
+/// A problem reading a movie, or replaying it against a ROM.
+#[derive(Debug)]
+pub enum MovieError {
+    /// The movie file couldn't be read or written.
+    Io(io::Error),
+    /// The file is not a movie.
+    NotAMovie,
+    /// The file was written by a different version of the interpreter.
+    UnsupportedVersion { found: u16 },
+    /// The movie was recorded with a different ROM.
+    WrongRom { expected: u64, found: u64 },
+    /// The file ends early, or holds quirks or a random number generator this version doesn't know.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(error) => write!(f, "{}", error),
+            MovieError::NotAMovie => write!(f, "not a movie file"),
+            MovieError::UnsupportedVersion { found } =>
+                write!(f, "movie is format version {}, but this interpreter reads version {}", found, FORMAT_VERSION),
+            MovieError::WrongRom { expected, found } =>
+                write!(f, "movie was recorded with ROM {:016x}, not the loaded ROM {:016x}", found, expected),
+            MovieError::Corrupt(reason) => write!(f, "movie is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(error: io::Error) -> Self { MovieError::Io(error) }
+}
+
+/// A recording of a session: everything needed to replay it exactly, given the same ROM.
+///
+/// Execution is deterministic apart from input and the random number generator, so a movie stores the generator's
+/// starting state, the quirks, and the keys pressed and instructions run in each frame.
+/// On disk, it is a header of the magic bytes `C8MV`, the format version, ROM hash, generator kind (0 for seeded,
+/// 1 for COSMAC VIP) and its 8-byte state (the seeded state, or R9 for the VIP), quirks (see
+/// [`Quirks::to_bits`]) and number of frames. For the VIP generator, the 256 bytes of the interpreter page it reads
+/// follow. Then come 3 bytes per frame: the key mask and the cycle count. All integers are little-endian.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie{
+    rom_hash: u64,
+    random: RandomSource,
+    quirks: Quirks,
+    frames: Vec<Frame>,
+}
+
+impl Movie{
+    /// Starts an empty movie of a ROM, run with the given random number generator (in its starting state) and quirks.
+    /// Scripted generators can't be recorded, so are recorded as a generator seeded with 0.
+    pub fn new(rom: &[u8], random: &RandomSource, quirks: Quirks) -> Self {
+        let random = match random {
+            RandomSource::Scripted { .. } => RandomSource::seeded(0),
+            random => random.clone(),
+        };
+        Movie { rom_hash: fnv1a(rom), random, quirks, frames: Vec::new() }
+    }
+
+    /// The hash of the ROM the movie was recorded with.
+    pub fn rom_hash(&self) -> u64 { self.rom_hash }
+
+    /// The CPU's random number generator, in its state at the start of the movie.
+    pub fn random(&self) -> &RandomSource { &self.random }
+
+    /// The quirks the movie was recorded with.
+    pub fn quirks(&self) -> Quirks { self.quirks }
+
+    /// The recorded frames, in order.
+    pub fn frames(&self) -> &[Frame] { &self.frames }
+
+    /// Appends a frame.
+    pub fn push_frame(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+
+    /// Counts one more instruction in the latest frame, for when a frame is recorded before its instructions are run.
+    pub fn count_cycle(&mut self) {
+        if let Some(frame) = self.frames.last_mut() { frame.cycles = frame.cycles.saturating_add(1); }
+    }
+
+    /// Checks the movie was recorded with the given ROM.
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), MovieError> {
+        let expected = fnv1a(rom);
+        if self.rom_hash != expected { return Err(MovieError::WrongRom { expected, found: self.rom_hash }); }
+        Ok(())
+    }
+
+    /// Creates a CPU set up to replay the movie: with its quirks, random number generator and the ROM loaded.
+    /// Fails if the ROM isn't the one the movie was recorded with.
+    pub fn create_cpu(&self, rom: &[u8]) -> Result<CPU, MovieError> {
+        self.check_rom(rom)?;
+        let mut cpu = CPU::new(self.quirks);
+        cpu.set_random_source(self.random.clone());
+        cpu.load(rom).map_err(|_| MovieError::Corrupt("ROM does not fit in memory"))?;
+        Ok(cpu)
+    }
+
+    /// Writes the movie to a file.
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Reads a movie from a file.
+    pub fn load(path: &Path) -> Result<Movie, MovieError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Serialises the movie in the file format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_SIZE + 3 * self.frames.len());
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&self.rom_hash.to_le_bytes());
+        let (kind, state, page) = match &self.random {
+            RandomSource::Vip { r9, page } => (1, *r9 as u64, &page[..]),
+            RandomSource::Seeded { state } => (0, *state, &[][..]),
+            RandomSource::Scripted { .. } => unreachable!("scripted generators are recorded as seeded"),
+        };
+        data.push(kind);
+        data.extend_from_slice(&state.to_le_bytes());
+        data.extend_from_slice(&self.quirks.to_bits().to_le_bytes());
+        data.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        data.extend_from_slice(page);
+        for frame in &self.frames {
+            data.extend_from_slice(&frame.keys.to_le_bytes());
+            data.push(frame.cycles);
+        }
+        data
+    }
+
+    /// Parses a movie in the file format.
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        if data.len() < 6 || &data[..4] != MAGIC { return Err(MovieError::NotAMovie); }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != FORMAT_VERSION { return Err(MovieError::UnsupportedVersion { found: version }); }
+        if data.len() < HEADER_SIZE { return Err(MovieError::Corrupt("file ends early")); }
+
+        let rom_hash = u64::from_le_bytes(data[6..14].try_into().unwrap());
+        let state = u64::from_le_bytes(data[15..23].try_into().unwrap());
+        let (random, page_size) = match data[14] {
+            0 => (RandomSource::Seeded { state }, 0),
+            1 if state >> 16 == 0 => {
+                let page = data.get(HEADER_SIZE..HEADER_SIZE + 256).ok_or(MovieError::Corrupt("file ends early"))?;
+                (RandomSource::Vip { r9: state as u16, page: Box::new(page.try_into().unwrap()) }, 256)
+            },
+            _ => return Err(MovieError::Corrupt("unknown random number generator")),
+        };
+        let quirks = Quirks::from_bits(u32::from_le_bytes(data[23..27].try_into().unwrap()))
+            .ok_or(MovieError::Corrupt("unknown quirks"))?;
+        let count = u32::from_le_bytes(data[27..31].try_into().unwrap()) as usize;
+
+        let body = &data[HEADER_SIZE + page_size..];
+        if body.len() != 3 * count { return Err(MovieError::Corrupt("frame count does not match file length")); }
+        let frames = body.chunks(3).map(|frame| Frame { keys: u16::from_le_bytes([frame[0], frame[1]]), cycles: frame[2] }).collect();
+        Ok(Movie { rom_hash, random, quirks, frames })
+    }
+}
@@ -0,0 +1,232 @@
+use chip8_interpreter::{audio::{Tone, Waveform}, chip8::{Quirks, RandomKind}, headless::ScreenFormat, disassembler::Syntax, filter::FilterMode, keymap::Keymap, palette::Palette, scheduler::{Timing, TIMER_HZ}, viewport::ScaleMode};
+
+/// What the program has been asked to do, selected by the first command-line argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Run a ROM (the default).
+    Run,
+    /// Disassemble a ROM (`disasm`).
+    Disassemble,
+    /// Assemble a source file into a ROM (`asm`).
+    Assemble,
+}
+
+/// Options parsed from the command line.
+pub struct Options{
+    pub mode: Mode,
+    pub rom_path: Option<String>, // Anything that isn't an option is taken to be the ROM path (or source file, for `asm`)
+    pub quirks: Quirks,
+    pub headless: bool, // Run without a window, then dump the final state
+    pub cycles: u64, // Number of instructions to run in headless mode
+    pub cycles_per_frame: u8,
+    pub instructions_per_second: Option<u32>, // CPU speed, instead of 60 times the cycles per frame
+    pub timing: Timing, // How long each instruction takes
+    pub format: ScreenFormat, // Format of the screen dump in headless mode
+    pub output: Option<String>, // File to write the headless dump, disassembly or assembled ROM to
+    pub debug: bool, // Start in the debugger
+    pub syntax: Syntax, // Assembly syntax used by the disassembler
+    pub start: Option<u16>, // Start of the memory range to disassemble, instead of the whole ROM
+    pub length: Option<u16>, // Length of the memory range to disassemble
+    pub rewind_speed: usize, // Frames stepped back per frame while rewinding
+    pub record: Option<String>, // File to record an input movie to
+    pub replay: Option<String>, // Movie to replay
+    pub seed: Option<u64>, // Seed for the random number generator, instead of a random one
+    pub rng: RandomKind, // Random number generator used by Cxkk
+    pub vip_interpreter: Option<String>, // Image of the COSMAC VIP's CHIP-8 interpreter, read by the VIP generator
+    pub keymap: Keymap, // Keyboard mapping, from a preset or a TOML file
+    pub palette: Palette, // Colours the screen is drawn in
+    pub filter: FilterMode, // Display filter applied at start-up
+    pub decay: f32, // Brightness kept per frame by fading pixels, with the blend filter
+    pub scale: u32, // Initial window size, in window pixels per low-resolution CHIP-8 pixel
+    pub scaling: ScaleMode, // How the screen is scaled to fill the window
+    pub screenshot: Option<String>, // Directory screenshots are written to; in headless mode, one is taken at the end
+    pub screenshot_scale: u32, // Image pixels per CHIP-8 pixel in screenshots
+    pub capture: Option<String>, // File (or directory, for a PNG sequence) to capture gameplay to from the start
+    pub capture_scale: u32, // Image pixels per high-resolution CHIP-8 pixel in captures
+    pub tone: Tone, // How the buzzer sounds
+    pub wav: Option<String>, // File to render the buzzer to
+}
+
+impl Options{
+    /// Parses the command-line arguments (excluding the program name).
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            mode: Mode::Run,
+            rom_path: None,
+            quirks: Quirks::default(),
+            headless: false,
+            cycles: 1000,
+            cycles_per_frame: 10,
+            instructions_per_second: None,
+            timing: Timing::Uniform,
+            format: ScreenFormat::Ascii,
+            output: None,
+            debug: false,
+            syntax: Syntax::default(),
+            start: None,
+            length: None,
+            rewind_speed: 2,
+            record: None,
+            replay: None,
+            seed: None,
+            rng: RandomKind::Seeded,
+            vip_interpreter: None,
+            keymap: Keymap::default(),
+            palette: Palette::default(),
+            filter: FilterMode::Off,
+            decay: 0.6,
+            scale: 20,
+            scaling: ScaleMode::Integer,
+            screenshot: None,
+            screenshot_scale: 10,
+            capture: None,
+            capture_scale: 4,
+            tone: Tone::default(),
+            wav: None,
+        };
+
+        // Individual quirks are applied on top of the preset, wherever `--quirks` appears
+        let mut quirk_overrides = Vec::new();
+        let mut arg_iter = args.iter().peekable();
+        match arg_iter.peek().map(|arg| arg.as_str()) {
+            Some("disasm") => { options.mode = Mode::Disassemble; arg_iter.next(); },
+            Some("asm") => { options.mode = Mode::Assemble; arg_iter.next(); },
+            _ => { },
+        }
+
+        while let Some(arg) = arg_iter.next() {
+            match arg.as_str() {
+                "--quirks" => {
+                    let name = Self::value(arg, arg_iter.next())?;
+                    options.quirks = Quirks::from_name(name)
+                        .ok_or(format!("Unknown quirks preset '{}'! Expected one of: vip, chip48, schip, xochip.", name))?;
+                },
+                "--quirk" => quirk_overrides.push(Self::value(arg, arg_iter.next())?),
+                "--headless" => options.headless = true,
+                "--debug" => options.debug = true,
+                "--cycles" => options.cycles = Self::number(arg, arg_iter.next())?,
+                "--cycles-per-frame" => options.cycles_per_frame = Self::number(arg, arg_iter.next())?,
+                "--ips" => options.instructions_per_second = Some(Self::number(arg, arg_iter.next())?),
+                "--timing" => {
+                    let name = Self::value(arg, arg_iter.next())?;
+                    options.timing = Timing::from_name(name)
+                        .ok_or(format!("Unknown timing '{}'! Expected one of: uniform, vip.", name))?;
+                },
+                "--format" => {
+                    let name = Self::value(arg, arg_iter.next())?;
+                    options.format = ScreenFormat::from_name(name)
+                        .ok_or(format!("Unknown screen format '{}'! Expected one of: ascii, pbm.", name))?;
+                },
+                "--syntax" => {
+                    let name = Self::value(arg, arg_iter.next())?;
+                    options.syntax = Syntax::from_name(name)
+                        .ok_or(format!("Unknown syntax '{}'! Expected one of: cowgod, octo.", name))?;
+                },
+                "--start" => options.start = Some(Self::address(arg, arg_iter.next())?),
+                "--length" => options.length = Some(Self::address(arg, arg_iter.next())?),
+                "--rewind-speed" => options.rewind_speed = Self::number(arg, arg_iter.next())?,
+                "--record" => options.record = Some(Self::value(arg, arg_iter.next())?.to_string()),
+                "--replay" => options.replay = Some(Self::value(arg, arg_iter.next())?.to_string()),
+                "--vip-interpreter" => options.vip_interpreter = Some(Self::value(arg, arg_iter.next())?.to_string()),
+                "--rng" => {
+                    let name = Self::value(arg, arg_iter.next())?;
+                    options.rng = RandomKind::from_name(name)
+                        .ok_or(format!("Unknown random number generator '{}'! Expected one of: seeded, vip.", name))?;
+                },
+                "--keymap" => options.keymap = Keymap::load(Self::value(arg, arg_iter.next())?)?,
+                "--palette" => options.palette = Palette::parse(Self::value(arg, arg_iter.next())?)?,
+                "--filter" => {
+                    let name = Self::value(arg, arg_iter.next())?;
+                    options.filter = FilterMode::from_name(name)
+                        .ok_or(format!("Unknown display filter '{}'! Expected one of: off, blend, or.", name))?;
+                },
+                "--decay" => {
+                    options.decay = Self::number(arg, arg_iter.next())?;
+                    if !(0.0..1.0).contains(&options.decay) {
+                        return Err(format!("Invalid decay '{}'! Expected a value from 0 up to (but not including) 1.", options.decay));
+                    }
+                },
+                "--scale" => {
+                    options.scale = Self::number(arg, arg_iter.next())?;
+                    if options.scale == 0 { return Err("Invalid scale '0'! Expected a whole number of at least 1.".to_string()); }
+                },
+                "--scaling" => {
+                    let name = Self::value(arg, arg_iter.next())?;
+                    options.scaling = ScaleMode::from_name(name)
+                        .ok_or(format!("Unknown scaling '{}'! Expected one of: integer, fit.", name))?;
+                },
+                "--screenshot" => options.screenshot = Some(Self::value(arg, arg_iter.next())?.to_string()),
+                "--screenshot-scale" => {
+                    options.screenshot_scale = Self::number(arg, arg_iter.next())?;
+                    if options.screenshot_scale == 0 { return Err("Invalid screenshot scale '0'! Expected a whole number of at least 1.".to_string()); }
+                },
+                "--capture" => options.capture = Some(Self::value(arg, arg_iter.next())?.to_string()),
+                "--capture-scale" => {
+                    options.capture_scale = Self::number(arg, arg_iter.next())?;
+                    if options.capture_scale == 0 { return Err("Invalid capture scale '0'! Expected a whole number of at least 1.".to_string()); }
+                },
+                "--waveform" => options.tone.waveform = Waveform::parse(Self::value(arg, arg_iter.next())?)?,
+                "--tone" => {
+                    options.tone.frequency = Self::number(arg, arg_iter.next())?;
+                    if !options.tone.frequency.is_finite() || options.tone.frequency <= 0.0 { return Err("Invalid tone! Expected a frequency in Hz above 0.".to_string()); }
+                },
+                "--volume" => {
+                    options.tone.volume = Self::number(arg, arg_iter.next())?;
+                    if !(0.0..=1.0).contains(&options.tone.volume) { return Err("Invalid volume! Expected a value from 0 to 1.".to_string()); }
+                },
+                "--attack" => options.tone.attack = Self::milliseconds(arg, arg_iter.next())?,
+                "--release" => options.tone.release = Self::milliseconds(arg, arg_iter.next())?,
+                "--wav" => options.wav = Some(Self::value(arg, arg_iter.next())?.to_string()),
+                "--seed" => options.seed = Some(Self::number(arg, arg_iter.next())?),
+                "--output" => options.output = Some(Self::value(arg, arg_iter.next())?.to_string()),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'!", arg)),
+                _ => options.rom_path = Some(arg.to_string()),
+            }
+        }
+
+        for quirk in quirk_overrides {
+            let (name, on) = match quirk.split_once('=') {
+                Some((name, "on" | "true" | "1")) => (name, true),
+                Some((name, "off" | "false" | "0")) => (name, false),
+                Some((_, value)) => return Err(format!("Invalid value '{}' for option '--quirk'! Expected on or off.", value)),
+                None => (quirk, true),
+            };
+            options.quirks.set(name, on)?;
+        }
+
+        Ok(options)
+    }
+
+    /// The CPU speed, from `--ips` or `--cycles-per-frame`.
+    pub fn speed(&self) -> u32 {
+        self.instructions_per_second.unwrap_or(self.cycles_per_frame as u32 * TIMER_HZ)
+    }
+
+    /// Gets the value following an option, failing if there isn't one.
+    fn value<'a>(option: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+        value.map(String::as_str).ok_or(format!("Missing value for option '{}'!", option))
+    }
+
+    /// Gets the address following an option, in decimal or hexadecimal with a `0x` prefix.
+    fn address(option: &str, value: Option<&String>) -> Result<u16, String> {
+        let value = Self::value(option, value)?;
+        let parsed = match value.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => value.parse(),
+        };
+        parsed.map_err(|_| format!("Invalid address '{}' for option '{}'!", value, option))
+    }
+
+    /// Gets a duration in milliseconds following an option, as seconds.
+    fn milliseconds(option: &str, value: Option<&String>) -> Result<f32, String> {
+        let milliseconds: f32 = Self::number(option, value)?;
+        if !milliseconds.is_finite() || milliseconds < 0.0 { return Err(format!("Invalid duration '{}' for option '{}'! Expected milliseconds, 0 or more.", milliseconds, option)); }
+        Ok(milliseconds / 1000.0)
+    }
+
+    /// Gets the numeric value following an option, failing if there isn't one or it isn't a number.
+    fn number<T: std::str::FromStr>(option: &str, value: Option<&String>) -> Result<T, String> {
+        let value = Self::value(option, value)?;
+        value.parse().map_err(|_| format!("Invalid number '{}' for option '{}'!", value, option))
+    }
+}
@@ -0,0 +1,96 @@
+/// An RGB colour.
+pub type Rgb = [u8; 3];
+
+/// The colours the screen is drawn in, indexed by each pixel's plane bitmask.
+/// Index 0 is the background, 1 is plane 1 only (the foreground), 2 is plane 2 only (XO-CHIP), and 3 is both planes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette{
+    name: String,
+    colours: [Rgb; 4],
+}
+
+/// The built-in palettes, in the order the cycle hotkey steps through them.
+const PRESETS: [(&str, [Rgb; 4]); 5] = [
+    // White on black, with greys for the extra XO-CHIP planes
+    ("classic", [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]]),
+    // P1 green phosphor
+    ("green", [[5, 20, 8], [51, 255, 102], [26, 140, 56], [38, 196, 79]]),
+    // P3 amber phosphor
+    ("amber", [[20, 12, 0], [255, 176, 0], [140, 96, 0], [196, 136, 0]]),
+    // Reflective grey LCD, as on the HP-48 calculators
+    ("lcd", [[190, 198, 180], [40, 46, 40], [120, 128, 112], [80, 86, 76]]),
+    // The defaults of the Octo IDE
+    ("octo", [[153, 102, 0], [255, 204, 0], [255, 102, 0], [102, 34, 0]]),
+];
+
+impl Palette{
+    /// Looks up a built-in palette by name: `classic`, `green`, `amber`, `lcd` or `octo`.
+    pub fn preset(name: &str) -> Option<Palette> {
+        PRESETS.iter().find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(name, colours)| Palette { name: name.to_string(), colours: *colours })
+    }
+
+    /// The names of the built-in palettes.
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESETS.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Creates a custom palette from a background and foreground colour, and optionally colours for plane 2 and for
+    /// both planes (XO-CHIP). If these are missing, they are blended from the background and foreground.
+    pub fn custom(background: Rgb, foreground: Rgb, plane2: Option<Rgb>, both: Option<Rgb>) -> Palette {
+        let blend = |weight: u16| -> Rgb {
+            let mut colour = [0; 3];
+            for channel in 0..3 {
+                colour[channel] = ((background[channel] as u16 * (3 - weight) + foreground[channel] as u16 * weight) / 3) as u8;
+            }
+            colour
+        };
+        Palette { name: "custom".to_string(), colours: [background, foreground, plane2.unwrap_or(blend(2)), both.unwrap_or(blend(1))] }
+    }
+
+    /// Parses a palette: a preset name, or 2 to 4 comma-separated hex colours (background, foreground, plane 2 and both
+    /// planes), e.g. `000000,33ff66` or `#1d2b53,#ff004d,#29adff,#ffec27`.
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        if let Some(palette) = Self::preset(text) { return Ok(palette); }
+        let not_a_palette = format!("'{}' is not a palette: expected one of {}, or 2 to 4 hex colours", text, Self::preset_names().join(", "));
+        if !text.contains(',') { return Err(not_a_palette); }
+
+        let colours = text.split(',').map(|colour| parse_colour(colour.trim())).collect::<Result<Vec<Rgb>, String>>()?;
+        match colours.as_slice() {
+            [background, foreground] => Ok(Self::custom(*background, *foreground, None, None)),
+            [background, foreground, plane2] => Ok(Self::custom(*background, *foreground, Some(*plane2), None)),
+            [background, foreground, plane2, both] => Ok(Self::custom(*background, *foreground, Some(*plane2), Some(*both))),
+            _ => Err(not_a_palette),
+        }
+    }
+
+    /// The palette's name, or `custom`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The colour of a pixel with the given plane bitmask.
+    pub fn colour(&self, planes: u8) -> Rgb {
+        self.colours[planes as usize & 0b11]
+    }
+
+    /// The built-in palette after this one, wrapping around. A custom palette is followed by the first preset.
+    pub fn next(&self) -> Palette {
+        let index = PRESETS.iter().position(|(name, _)| *name == self.name).map(|index| index + 1).unwrap_or(0);
+        let (name, colours) = PRESETS[index % PRESETS.len()];
+        Palette { name: name.to_string(), colours }
+    }
+}
+
+impl Default for Palette {
+    /// Defaults to white on black.
+    fn default() -> Self { Palette::preset("classic").unwrap() }
+}
+
+/// Parses a colour written as 6 hex digits, with an optional `#`.
+fn parse_colour(text: &str) -> Result<Rgb, String> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)
+        .ok_or(format!("'{}' is not a colour: expected 6 hex digits, e.g. ff8800", text))?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
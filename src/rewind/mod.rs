@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+use crate::chip8::{CPU, SnapshotError};
+
+/// Unchanged runs shorter than this are stored in a delta rather than starting a new run, as each run costs 6 bytes.
+const MIN_GAP: usize = 6;
+
+/// A history of CPU states, one per frame, for rewinding.
+///
+/// Only the most recent snapshot is kept in full. Every older frame is stored as a delta which turns the snapshot of
+/// the frame after it back into its own snapshot, holding just the bytes that changed between the two frames.
+/// Once `capacity` frames are stored, the oldest is dropped to make room.
+pub struct RewindBuffer{
+    capacity: usize,
+    latest: Option<Vec<u8>>, // Snapshot of the most recently recorded frame
+    deltas: VecDeque<Vec<u8>>, // Deltas back to each older frame, oldest first
+}
+
+impl RewindBuffer{
+    /// Creates an empty history holding up to `capacity` frames (at least one).
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer { capacity: capacity.max(1), latest: None, deltas: VecDeque::new() }
+    }
+
+    /// The number of frames that can currently be rewound, not counting the latest.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Returns true if nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Discards the whole history.
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    /// Records the current state of the CPU as the latest frame.
+    pub fn push(&mut self, cpu: &CPU) {
+        let snapshot = cpu.snapshot();
+        if let Some(previous) = self.latest.take() {
+            self.deltas.push_back(encode_delta(&snapshot, &previous));
+            if self.deltas.len() >= self.capacity { self.deltas.pop_front(); }
+        }
+        self.latest = Some(snapshot);
+    }
+
+    /// Steps back up to `frames` frames, restoring the CPU to the frame reached.
+    /// The oldest frame is never discarded, so holding rewind stops there. Returns the number of frames stepped back.
+    pub fn rewind(&mut self, frames: usize, cpu: &mut CPU) -> Result<usize, SnapshotError> {
+        let latest = match self.latest.as_mut() { Some(latest) => latest, None => return Ok(0) };
+        let mut stepped = 0;
+        while stepped < frames {
+            match self.deltas.pop_back() {
+                Some(delta) => apply_delta(latest, &delta),
+                None => break,
+            }
+            stepped += 1;
+        }
+        cpu.restore(latest)?;
+        Ok(stepped)
+    }
+}
+
+/// Encodes the bytes of `target` which differ from `base`, so that `apply_delta(base, delta)` gives `target`.
+/// The delta is a list of runs: a 4-byte offset, a 2-byte length, then the bytes of `target` in that run.
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut position = 0;
+    while position < target.len() {
+        if base[position] == target[position] { position += 1; continue; }
+
+        // Extend the run until the bytes have matched for MIN_GAP in a row (or the run is as long as it can be)
+        let start = position;
+        let mut end = position + 1;
+        let mut scan = end;
+        while scan < target.len() && scan - end < MIN_GAP && scan - start < u16::MAX as usize {
+            if base[scan] != target[scan] { end = scan + 1; }
+            scan += 1;
+        }
+
+        delta.extend_from_slice(&(start as u32).to_le_bytes());
+        delta.extend_from_slice(&((end - start) as u16).to_le_bytes());
+        delta.extend_from_slice(&target[start..end]);
+        position = end;
+    }
+    delta
+}
+
+/// Applies a delta made by `encode_delta` in place.
+fn apply_delta(data: &mut [u8], delta: &[u8]) {
+    let mut position = 0;
+    while position + 6 <= delta.len() {
+        let start = u32::from_le_bytes(delta[position..position + 4].try_into().unwrap()) as usize;
+        let length = u16::from_le_bytes([delta[position + 4], delta[position + 5]]) as usize;
+        position += 6;
+        data[start..start + length].copy_from_slice(&delta[position..position + length]);
+        position += length;
+    }
+}
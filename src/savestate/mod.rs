@@ -0,0 +1,193 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::chip8::{CPU, Quirks, SnapshotError, fnv1a};
+
+/// Number of save slots kept for each ROM.
+pub const SLOT_COUNT: usize = 10;
+
+/// Version of the save file format. Bumped whenever the layout of the file or of CPU snapshots changes.
+pub const FORMAT_VERSION: u16 = 3;
+
+/// Identifies a save file.
+const MAGIC: &[u8; 4] = b"C8SV";
+
+/// Size of the file header: magic, format version, ROM hash and quirks.
+const HEADER_SIZE: usize = 4 + 2 + 8 + 4;
+
+/// A problem reading or writing a save slot.
+#[derive(Debug)]
+pub enum SaveStateError {
+    /// The save file couldn't be read or written.
+    Io(io::Error),
+    /// The file is not a save file.
+    NotASaveFile,
+    /// The file was written by a different version of the interpreter.
+    UnsupportedVersion { found: u16 },
+    /// The file holds states for a different ROM.
+    WrongRom { expected: u64, found: u64 },
+    /// The file holds states saved with different quirks, which would behave differently if loaded.
+    WrongQuirks { expected: Quirks, found: Quirks },
+    /// Slot numbers run from 0 to 9.
+    InvalidSlot(usize),
+    /// Nothing has been saved in the slot.
+    EmptySlot(usize),
+    /// The file ends early or a state in it can't be loaded.
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(error) => write!(f, "{}", error),
+            SaveStateError::NotASaveFile => write!(f, "not a save file"),
+            SaveStateError::UnsupportedVersion { found } =>
+                write!(f, "save file is format version {}, but this interpreter reads version {}", found, FORMAT_VERSION),
+            SaveStateError::WrongRom { expected, found } =>
+                write!(f, "save file is for ROM {:016x}, not the loaded ROM {:016x}", found, expected),
+            SaveStateError::WrongQuirks { expected, found } =>
+                write!(f, "save file was written with the {} quirks, but the interpreter is running with the {} quirks; \
+                    run with the same quirks, or delete the file to save afresh", describe(found), describe(expected)),
+            SaveStateError::InvalidSlot(slot) => write!(f, "there is no slot {} (slots are 0 to {})", slot, SLOT_COUNT - 1),
+            SaveStateError::EmptySlot(slot) => write!(f, "slot {} is empty", slot),
+            SaveStateError::Corrupt(reason) => write!(f, "save file is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+/// Names quirks by their preset, or by their bits (see [`Quirks::to_bits`]) if they don't match one.
+fn describe(quirks: &Quirks) -> String {
+    match quirks.preset_name() {
+        Some(name) => name.to_string(),
+        None => format!("custom (0x{:02x})", quirks.to_bits()),
+    }
+}
+
+impl From<io::Error> for SaveStateError {
+    fn from(error: io::Error) -> Self { SaveStateError::Io(error) }
+}
+
+impl From<SnapshotError> for SaveStateError {
+    fn from(error: SnapshotError) -> Self { SaveStateError::Corrupt(error.to_string()) }
+}
+
+/// A saved state, as stored in a slot.
+struct Slot {
+    timestamp: u64, // Seconds since the Unix epoch when the state was saved
+    snapshot: Vec<u8>, // The CPU state, from `CPU::snapshot`
+}
+
+/// The numbered save slots for one ROM, stored together in a single file named after the ROM's hash.
+///
+/// The file starts with a header of the magic bytes `C8SV`, the format version, the ROM hash and the quirks the states
+/// were saved with (see [`Quirks::to_bits`]); states can only be loaded by a CPU with the same quirks.
+/// Each of the ten slots follows: the time it was saved (0 if empty), the length of its snapshot, then the snapshot.
+/// All integers are little-endian.
+pub struct SaveSlots{
+    path: PathBuf,
+    rom_hash: u64,
+}
+
+impl SaveSlots{
+    /// Creates the save slots for a ROM, kept in `directory`.
+    pub fn new(directory: &Path, rom: &[u8]) -> Self {
+        let rom_hash = fnv1a(rom);
+        SaveSlots { path: directory.join(format!("{:016x}.sav", rom_hash)), rom_hash }
+    }
+
+    /// Creates the save slots for a ROM in the user's data directory (e.g. `%APPDATA%\chip8_interpreter\saves` on
+    /// Windows, or `~/.local/share/chip8_interpreter/saves` on Linux). Returns None if there is no data directory.
+    pub fn in_user_data_dir(rom: &[u8]) -> Option<Self> {
+        let directory = dirs::data_dir()?.join("chip8_interpreter").join("saves");
+        Some(Self::new(&directory, rom))
+    }
+
+    /// The file the slots are stored in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Saves the CPU state into a slot, replacing anything already there. The other slots are kept.
+    /// Fails without changing the file if it can't be read or is incompatible (e.g., written by another version, or
+    /// with different quirks), so that the states already in it aren't lost.
+    pub fn save(&self, slot: usize, cpu: &CPU) -> Result<(), SaveStateError> {
+        if slot >= SLOT_COUNT { return Err(SaveStateError::InvalidSlot(slot)); }
+
+        let mut slots = match self.read(cpu.quirks()) {
+            Err(SaveStateError::Io(error)) if error.kind() == io::ErrorKind::NotFound => (0..SLOT_COUNT).map(|_| None).collect(),
+            result => result?,
+        };
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        slots[slot] = Some(Slot { timestamp, snapshot: cpu.snapshot() });
+
+        if let Some(directory) = self.path.parent() { fs::create_dir_all(directory)?; }
+        fs::write(&self.path, self.encode(&slots, cpu.quirks()))?;
+        Ok(())
+    }
+
+    /// Loads the state in a slot into the CPU. The CPU is left unchanged if the slot can't be loaded, e.g., if it was
+    /// saved with different quirks.
+    /// Returns the time the state was saved, in seconds since the Unix epoch.
+    pub fn load(&self, slot: usize, cpu: &mut CPU) -> Result<u64, SaveStateError> {
+        if slot >= SLOT_COUNT { return Err(SaveStateError::InvalidSlot(slot)); }
+        let slots = match self.read(cpu.quirks()) {
+            Err(SaveStateError::Io(error)) if error.kind() == io::ErrorKind::NotFound => return Err(SaveStateError::EmptySlot(slot)),
+            result => result?,
+        };
+        let saved = slots[slot].as_ref().ok_or(SaveStateError::EmptySlot(slot))?;
+        cpu.restore(&saved.snapshot)?;
+        Ok(saved.timestamp)
+    }
+
+    /// Reads every slot from the file, checking the header (including that the states were saved with `quirks`) first.
+    fn read(&self, quirks: Quirks) -> Result<Vec<Option<Slot>>, SaveStateError> {
+        let data = fs::read(&self.path)?;
+        if data.len() < HEADER_SIZE || &data[..4] != MAGIC { return Err(SaveStateError::NotASaveFile); }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != FORMAT_VERSION { return Err(SaveStateError::UnsupportedVersion { found: version }); }
+        let rom_hash = u64::from_le_bytes(data[6..14].try_into().unwrap());
+        if rom_hash != self.rom_hash { return Err(SaveStateError::WrongRom { expected: self.rom_hash, found: rom_hash }); }
+        let found = Quirks::from_bits(u32::from_le_bytes(data[14..18].try_into().unwrap()))
+            .ok_or(SaveStateError::Corrupt("unknown quirks".to_string()))?;
+        if found != quirks { return Err(SaveStateError::WrongQuirks { expected: quirks, found }); }
+
+        let mut position = HEADER_SIZE;
+        let mut take = |length: usize| -> Result<&[u8], SaveStateError> {
+            let bytes = data.get(position..position + length).ok_or(SaveStateError::Corrupt("file ends early".to_string()))?;
+            position += length;
+            Ok(bytes)
+        };
+        let mut slots = Vec::with_capacity(SLOT_COUNT);
+        for _slot in 0..SLOT_COUNT {
+            let timestamp = u64::from_le_bytes(take(8)?.try_into().unwrap());
+            let length = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            let snapshot = take(length)?.to_vec();
+            slots.push(if timestamp == 0 && length == 0 { None } else { Some(Slot { timestamp, snapshot }) });
+        }
+        Ok(slots)
+    }
+
+    /// Writes the header and every slot.
+    fn encode(&self, slots: &[Option<Slot>], quirks: Quirks) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&self.rom_hash.to_le_bytes());
+        data.extend_from_slice(&quirks.to_bits().to_le_bytes());
+        for slot in slots {
+            let (timestamp, snapshot) = match slot {
+                Some(slot) => (slot.timestamp, slot.snapshot.as_slice()),
+                None => (0, &[][..]),
+            };
+            data.extend_from_slice(&timestamp.to_le_bytes());
+            data.extend_from_slice(&(snapshot.len() as u32).to_le_bytes());
+            data.extend_from_slice(snapshot);
+        }
+        data
+    }
+}
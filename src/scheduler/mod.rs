@@ -0,0 +1,147 @@
+use std::time::{Duration, Instant};
+use crate::chip8::{CPU, Instruction, VIP_EXECUTION_CYCLES};
+
+/// Rate at which the delay and sound timers count down, and frames are emulated, in Hz.
+pub const TIMER_HZ: u32 = 60;
+
+/// Highest supported speed: 255 instructions per frame, the most a movie frame can record.
+pub const MAX_INSTRUCTIONS_PER_SECOND: u32 = 255 * TIMER_HZ;
+
+/// Most frames emulated to catch up after the host falls behind, e.g., while the window is being dragged.
+/// Any further backlog is dropped, so the game slows down rather than racing to catch up.
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// How long each instruction takes, and so how many run in each frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes the same time, and the instructions per second are shared evenly between frames.
+    Uniform,
+    /// Every instruction takes as many machine cycles as on the COSMAC VIP (see [`CPU::vip_cycles`]), out of the
+    /// cycles each frame leaves for the interpreter. As on the VIP, `Dxyn` waits for the start of the next frame
+    /// before drawing. The instructions per second are ignored.
+    Vip,
+}
+
+impl Timing {
+    /// Looks up a timing mode by name (`uniform` or `vip`).
+    pub fn from_name(name: &str) -> Option<Timing> {
+        match name.to_lowercase().as_str() {
+            "uniform" => Some(Timing::Uniform),
+            "vip" => Some(Timing::Vip),
+            _ => None,
+        }
+    }
+}
+
+/// A fixed-timestep scheduler, which decouples emulation from the rate the display is refreshed at.
+///
+/// Emulation proceeds in frames of 1/60 of a second: each frame ticks the timers once and runs its share of the
+/// instructions per second. [`Scheduler::advance`] turns real time into a number of frames to emulate, so the timers
+/// run at exactly 60Hz and the CPU at the configured speed however often the screen is drawn. Within a frame,
+/// [`Scheduler::may_run`] decides whether each instruction runs in this frame or must wait for the next.
+#[derive(Clone, Debug)]
+pub struct Scheduler{
+    instructions_per_second: u32,
+    timing: Timing,
+    instruction_remainder: u32, // Instructions owed from previous frames, in 60ths of an instruction
+    frame_instructions: u32, // Instructions left to run in this frame, with uniform timing
+    frame_cycles: i64, // VIP machine cycles left in this frame; negative if the last instruction overran the frame
+    frame_started: bool, // Set once an instruction has run in this frame
+    backlog: u128, // Real time not yet emulated, in 60ths of a nanosecond
+    last: Option<Instant>, // When the scheduler was last advanced, or None if it hasn't started yet
+}
+
+impl Scheduler{
+    /// Creates a scheduler running `instructions_per_second` (between 1 and [`MAX_INSTRUCTIONS_PER_SECOND`]).
+    pub fn new(instructions_per_second: u32) -> Self {
+        Scheduler {
+            instructions_per_second: Self::clamp(instructions_per_second), timing: Timing::Uniform, instruction_remainder: 0,
+            frame_instructions: 0, frame_cycles: 0, frame_started: false, backlog: 0, last: None,
+        }
+    }
+
+    /// The number of instructions run per second of emulated time.
+    pub fn instructions_per_second(&self) -> u32 { self.instructions_per_second }
+
+    /// Changes the speed, to between 1 and [`MAX_INSTRUCTIONS_PER_SECOND`] instructions per second.
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.instructions_per_second = Self::clamp(instructions_per_second);
+    }
+
+    /// How long each instruction takes.
+    pub fn timing(&self) -> Timing { self.timing }
+
+    /// Changes how long each instruction takes, from the next frame.
+    pub fn set_timing(&mut self, timing: Timing) { self.timing = timing; }
+
+    /// Advances to the time `now`, returning the number of frames that are now due to be emulated.
+    /// The first call starts the clock, so returns 0. At most a few frames are returned at once; beyond that, the
+    /// time is dropped.
+    pub fn advance(&mut self, now: Instant) -> u32 {
+        let elapsed = self.last.map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.last = Some(now);
+        self.backlog += elapsed.as_nanos() * TIMER_HZ as u128;
+        let due = self.backlog / NANOS_PER_SECOND;
+        self.backlog %= NANOS_PER_SECOND;
+        due.min(MAX_CATCH_UP_FRAMES as u128) as u32
+    }
+
+    /// The real time until the next frame is due.
+    pub fn until_next_frame(&self) -> Duration {
+        let remaining = (NANOS_PER_SECOND - self.backlog).div_ceil(TIMER_HZ as u128);
+        Duration::from_nanos(remaining as u64)
+    }
+
+    /// Forgets any real time not yet emulated, and restarts the clock at the next call to [`Scheduler::advance`],
+    /// e.g., after execution has been paused.
+    pub fn reset(&mut self) {
+        self.backlog = 0;
+        self.last = None;
+    }
+
+    /// Starts emulating a frame. If the speed isn't a multiple of 60 instructions per second, frames run different
+    /// numbers of instructions so that the speed is exact over each second.
+    pub fn begin_frame(&mut self) {
+        self.instruction_remainder += self.instructions_per_second;
+        self.frame_instructions = self.instruction_remainder / TIMER_HZ;
+        self.instruction_remainder %= TIMER_HZ;
+        // Overrunning the last frame delays this one
+        self.frame_cycles = self.frame_cycles.min(0) + VIP_EXECUTION_CYCLES as i64;
+        self.frame_started = false;
+    }
+
+    /// Returns true if the CPU's next instruction runs in this frame, in which case it is counted against the frame.
+    /// Otherwise, the frame is over, and the instruction must wait for [`Scheduler::begin_frame`].
+    pub fn may_run(&mut self, cpu: &CPU) -> bool {
+        match self.timing {
+            Timing::Uniform => {
+                if self.frame_instructions == 0 { return false; }
+                self.frame_instructions -= 1;
+            },
+            Timing::Vip => {
+                if self.frame_cycles <= 0 { return false; }
+                // The VIP draws at the start of the next frame, so the rest of this one is spent waiting
+                if self.frame_started && matches!(cpu.next_instruction(), Some(Instruction::Draw(..))) {
+                    self.frame_cycles = 0;
+                    return false;
+                }
+                self.frame_cycles -= cpu.vip_cycles() as i64;
+            },
+        }
+        self.frame_started = true;
+        true
+    }
+
+    /// Ends the frame early, after the CPU returned [`StepOutcome::DisplayWait`](crate::chip8::StepOutcome::DisplayWait).
+    /// With VIP timing, a draw already waits for the next frame, so this does nothing.
+    pub fn end_frame(&mut self) {
+        if self.timing == Timing::Uniform { self.frame_instructions = 0; }
+    }
+
+    /// Clamps a speed to the supported range.
+    fn clamp(instructions_per_second: u32) -> u32 {
+        instructions_per_second.clamp(1, MAX_INSTRUCTIONS_PER_SECOND)
+    }
+}
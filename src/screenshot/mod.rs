@@ -0,0 +1,68 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::chip8::CPU;
+use crate::palette::Palette;
+
+/// Writes screenshots of a game to a directory, named after the ROM and the frame they were taken on.
+pub struct Screenshots{
+    directory: PathBuf,
+    rom_name: String, // File stem of the ROM, used to name the screenshots
+    scale: u32, // Image pixels per CHIP-8 pixel
+}
+
+impl Screenshots{
+    /// Creates a writer of screenshots of the ROM at `rom_path`, scaled by `scale` (at least 1) image pixels per
+    /// CHIP-8 pixel. The directory is created when the first screenshot is taken.
+    pub fn new(directory: &Path, rom_path: &Path, scale: u32) -> Self {
+        let rom_name = rom_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_else(|| "screenshot".to_string());
+        Screenshots { directory: directory.to_path_buf(), rom_name, scale: scale.max(1) }
+    }
+
+    /// The path the screenshot of the given frame is written to, e.g., `breakout_frame000120.png`.
+    pub fn path(&self, frame: u64) -> PathBuf {
+        self.directory.join(format!("{}_frame{:06}.png", self.rom_name, frame))
+    }
+
+    /// The path a gameplay clip starting on the given frame is written to, e.g., `breakout_frame000120.gif`.
+    pub fn clip_path(&self, frame: u64) -> PathBuf {
+        self.path(frame).with_extension("gif")
+    }
+
+    /// Writes the current screen in the given palette to a PNG file, returning its path.
+    pub fn save(&self, cpu: &CPU, palette: &Palette, frame: u64) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.directory)?;
+        let path = self.path(frame);
+        let mut out = BufWriter::new(File::create(&path)?);
+        write_png(cpu, palette, self.scale, &mut out)?;
+        out.flush()?;
+        Ok(path)
+    }
+}
+
+/// Renders the current screen in the given palette as 8-bit RGB pixels, row by row, scaled by `scale` image pixels
+/// per CHIP-8 pixel. Returns the width and height of the image along with its pixels.
+pub fn render(cpu: &CPU, palette: &Palette, scale: u32) -> (u32, u32, Vec<u8>) {
+    let scale = scale.max(1) as usize;
+    let (width, height) = (cpu.screen_width() * scale, cpu.screen_height() * scale);
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for row in cpu.screen.iter().take(cpu.screen_height()) {
+        let line: Vec<u8> = row.iter().take(cpu.screen_width())
+            .flat_map(|pixel| palette.colour(pixel & 0b11).repeat(scale))
+            .collect();
+        for _ in 0..scale { pixels.extend_from_slice(&line); }
+    }
+    (width as u32, height as u32, pixels)
+}
+
+/// Encodes the current screen in the given palette as a PNG image, scaled by `scale` image pixels per CHIP-8 pixel.
+pub fn write_png<W: Write>(cpu: &CPU, palette: &Palette, scale: u32, out: &mut W) -> io::Result<()> {
+    let (width, height, pixels) = render(cpu, palette, scale);
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+/// How the CHIP-8 screen is scaled to fill a window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Every CHIP-8 pixel is the same whole number of window pixels, so the image stays sharp.
+    Integer,
+    /// The screen fills as much of the window as it can, so pixels may differ in size by one window pixel.
+    Fit,
+}
+
+impl ScaleMode {
+    /// Looks up a scale mode by name (`integer` or `fit`).
+    pub fn from_name(name: &str) -> Option<ScaleMode> {
+        match name.to_lowercase().as_str() {
+            "integer" => Some(ScaleMode::Integer),
+            "fit" => Some(ScaleMode::Fit),
+            _ => None,
+        }
+    }
+}
+
+/// The area of a window the CHIP-8 screen is drawn in: the largest area with the screen's aspect ratio, centred,
+/// with the rest of the window letterboxed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport{
+    pub x: i32, // Top-left corner of the screen area, in window pixels
+    pub y: i32,
+    pub width: u32, // Size of the screen area, in window pixels
+    pub height: u32,
+    columns: usize, // Resolution of the CHIP-8 screen
+    rows: usize,
+}
+
+impl Viewport{
+    /// Lays out a screen of `columns` by `rows` pixels in a window of the given size.
+    /// The screen is always at least one window pixel per CHIP-8 pixel, so is cropped if the window is smaller than that.
+    pub fn new(window_width: u32, window_height: u32, columns: usize, rows: usize, mode: ScaleMode) -> Viewport {
+        let scale = (window_width as f64 / columns as f64).min(window_height as f64 / rows as f64);
+        let scale = match mode {
+            ScaleMode::Integer => scale.floor().max(1.0),
+            ScaleMode::Fit => scale.max(1.0),
+        };
+        let width = (columns as f64 * scale).floor() as u32;
+        let height = (rows as f64 * scale).floor() as u32;
+        Viewport {
+            x: (window_width as i32 - width as i32) / 2, y: (window_height as i32 - height as i32) / 2,
+            width, height, columns, rows,
+        }
+    }
+
+    /// The area covered by a CHIP-8 pixel, as (x, y, width, height) in window pixels.
+    /// Adjacent pixels share their edges exactly, so the screen has no gaps even when the scale isn't a whole number.
+    pub fn pixel(&self, x: usize, y: usize) -> (i32, i32, u32, u32) {
+        let left = x as u64 * self.width as u64 / self.columns as u64;
+        let right = (x + 1) as u64 * self.width as u64 / self.columns as u64;
+        let top = y as u64 * self.height as u64 / self.rows as u64;
+        let bottom = (y + 1) as u64 * self.height as u64 / self.rows as u64;
+        (self.x + left as i32, self.y + top as i32, (right - left) as u32, (bottom - top) as u32)
+    }
+}
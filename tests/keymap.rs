@@ -0,0 +1,40 @@
+//! Parses keymap files.
+
+use chip8_interpreter::keymap::{Binding, Hotkey, Keymap};
+
+#[test]
+fn rebinding_replaces_the_preset() {
+    let keymap = Keymap::from_toml("[keys]\n5 = \"Up\"\n[hotkeys]\nquit = \"F8\"").unwrap();
+    assert!(keymap.keys().contains(&(Binding::Key("Up".to_string()), 5)));
+    assert!(!keymap.keys().contains(&(Binding::Key("W".to_string()), 5)));
+    assert_eq!(keymap.hotkeys().iter().filter(|(_, hotkey)| *hotkey == Hotkey::Quit).count(), 1);
+}
+
+#[test]
+fn a_key_bound_to_two_hotkeys_is_rejected() {
+    // F12 takes screenshots in the preset
+    let error = Keymap::from_toml("[hotkeys]\nquit = \"F12\"").unwrap_err();
+    assert_eq!(error, "'F12' is bound to both hotkey screenshot and hotkey quit");
+}
+
+#[test]
+fn a_key_bound_to_a_hotkey_and_the_keypad_is_rejected() {
+    let error = Keymap::from_toml("[keys]\n0 = \"o\"").unwrap_err();
+    assert_eq!(error, "'O' is bound to both CHIP-8 key 0 and hotkey save_state");
+    let error = Keymap::from_toml("[keys]\n0 = \"Q\"").unwrap_err();
+    assert_eq!(error, "'Q' is bound to both CHIP-8 key 4 and CHIP-8 key 0");
+}
+
+#[test]
+fn repeating_a_binding_for_one_action_is_allowed() {
+    assert!(Keymap::from_toml("[hotkeys]\nspeed_up = [\"=\", \"=\"]").is_ok());
+}
+
+#[test]
+fn the_readme_example_is_valid() {
+    let readme = include_str!("../readme.md");
+    let start = readme.find("```toml\n").expect("readme should have a keymap example") + "```toml\n".len();
+    let example = &readme[start..start + readme[start..].find("```").unwrap()];
+    let keymap = Keymap::from_toml(example).unwrap();
+    assert!(keymap.hotkeys().contains(&(Binding::Key("F8".to_string()), Hotkey::Quit)));
+}
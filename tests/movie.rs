@@ -0,0 +1,38 @@
+//! Writes movies to bytes and reads them back.
+
+use chip8_interpreter::chip8::{Quirks, RandomSource};
+use chip8_interpreter::movie::{Frame, Movie};
+
+const ROM: [u8; 4] = [0xC0, 0xFF, 0x12, 0x00]; // RND V0, 0xFF; JP 0x200
+
+/// Records a few frames of the ROM run with the given generator.
+fn recorded(random: &RandomSource) -> Movie {
+    let mut movie = Movie::new(&ROM, random, Quirks::VIP);
+    for keys in [0, 1, 0x8000] { movie.push_frame(Frame { keys, cycles: 10 }); }
+    movie
+}
+
+#[test]
+fn seeded_movies_round_trip() {
+    let movie = recorded(&RandomSource::seeded(42));
+    assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+}
+
+#[test]
+fn vip_movies_keep_the_interpreter_page() {
+    let interpreter: Vec<u8> = (0..=255).map(|_| 0).chain((0..=255).rev()).collect();
+    let movie = recorded(&RandomSource::vip(0x1234, &interpreter).unwrap());
+    let bytes = movie.to_bytes();
+    let read = Movie::from_bytes(&bytes).unwrap();
+    assert_eq!(read, movie);
+
+    // Replaying without the interpreter file gives the same random numbers
+    let mut original = movie.create_cpu(&ROM).unwrap();
+    let mut replayed = read.create_cpu(&ROM).unwrap();
+    for _ in 0..20 {
+        original.step().unwrap();
+        replayed.step().unwrap();
+        assert_eq!(replayed.registers(), original.registers());
+    }
+    assert!(Movie::from_bytes(&bytes[..bytes.len() - 3 * 3 - 1]).is_err());
+}
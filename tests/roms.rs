@@ -0,0 +1,200 @@
+//! Runs small assembled ROMs headlessly and compares hashes of the final screen.
+//! If a change to drawing is intended, print the screen with `--headless` to check it before updating a hash.
+
+use chip8_interpreter::assembler::assemble;
+use chip8_interpreter::chip8::{fnv1a, CpuError, Quirks, CPU};
+use chip8_interpreter::headless::HeadlessRunner;
+
+/// Draws the sixteen hex digits of the font in two rows.
+const FONT_GRID: &str = "
+    LD V0, 0        ; Digit
+    LD V1, 1        ; X
+    LD V2, 1        ; Y
+loop:
+    LD F, V0
+    DRW V1, V2, 5
+    ADD V0, 1
+    ADD V1, 8
+    SE V0, 8
+    JP next
+    LD V1, 1
+    LD V2, 8
+next:
+    SE V0, 16
+    JP loop
+    EXIT
+";
+
+/// Prints 0, 9, 10, 99, 100 and 255 in decimal, using `Fx33`.
+const BCD: &str = "
+    LD V5, 0        ; Offset into the table
+next:
+    LD I, table
+    ADD I, V5
+    LD V2, [I]      ; V0 = value, V1 = x, V2 = y
+    LD V9, V1
+    LD VA, V2
+    LD I, digits
+    LD B, V0
+    LD V2, [I]
+    CALL digit
+    LD V0, V1
+    CALL digit
+    LD V0, V2
+    CALL digit
+    ADD V5, 3
+    SE V5, 18
+    JP next
+    EXIT
+
+digit:
+    LD F, V0
+    DRW V9, VA, 5
+    ADD V9, 5
+    RET
+
+table:
+    DB 0, 1, 1
+    DB 9, 1, 9
+    DB 10, 1, 17
+    DB 99, 24, 1
+    DB 100, 24, 9
+    DB 255, 24, 17
+digits:
+    DB 0, 0, 0
+";
+
+/// Draws a box over the bottom-right corner, which is clipped or wrapped depending on the quirks.
+const CORNER: &str = "
+    LD V0, 60
+    LD V1, 29
+    LD I, box
+    DRW V0, V1, 6
+    EXIT
+box:
+    SPRITE \"########\", \"#......#\", \"#......#\", \"#......#\", \"#......#\", \"########\"
+";
+
+/// Switches to high resolution, draws large digits and a 16x16 sprite, then scrolls.
+const SCHIP: &str = "
+    HIGH
+    LD V0, 0xA
+    LD V1, 4
+    LD V2, 4
+    LD HF, V0
+    DRW V1, V2, 10
+    LD V1, 100
+    LD V2, 40
+    LD I, ball
+    DRW V1, V2, 0
+    SCD 4
+    SCR
+    EXIT
+ball:
+    SPRITE \"......####......\", \"....########....\", \"...##########...\", \"..############..\"
+    SPRITE \".##############.\", \".##############.\", \"################\", \"################\"
+    SPRITE \"################\", \"################\", \".##############.\", \".##############.\"
+    SPRITE \"..############..\", \"...##########...\", \"....########....\", \"......####......\"
+";
+
+/// Draws overlapping squares on each XO-CHIP plane.
+const PLANES: &str = "
+    LD V0, 8
+    LD V1, 8
+    PLANE 1
+    LD I, square
+    DRW V0, V1, 8
+    ADD V0, 4
+    ADD V1, 4
+    PLANE 2
+    DRW V0, V1, 8
+    ADD V0, 4
+    ADD V1, 4
+    PLANE 3
+    LD I, LONG both
+    DRW V0, V1, 4
+    EXIT
+square:
+    DB 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF
+both:
+    DB 0xF0, 0xF0, 0xF0, 0xF0
+    DB 0x0F, 0x0F, 0x0F, 0x0F
+";
+
+/// Recurses `depth` subroutine calls deep, counting in V0, then unwinds.
+fn recursion(depth: u8) -> String {
+    format!("
+    CALL recurse
+    EXIT
+recurse:
+    ADD V0, 1
+    SE V0, {}
+    CALL recurse
+    RET
+", depth)
+}
+
+/// Assembles a program and runs it headlessly until it exits.
+fn run(source: &str, quirks: Quirks) -> Result<CPU, CpuError> {
+    let rom = assemble(source, "test.asm").expect("program should assemble");
+    let mut cpu = CPU::new(quirks);
+    cpu.seed_rng(1);
+    cpu.load(&rom)?;
+    let exited = HeadlessRunner::new(10).run(&mut cpu, 10_000)?;
+    assert!(exited, "program should exit");
+    Ok(cpu)
+}
+
+/// Hashes the whole screen buffer, including the bits for the second plane.
+fn screen_hash(cpu: &CPU) -> u64 {
+    let pixels: Vec<u8> = cpu.screen.iter().flatten().copied().collect();
+    fnv1a(&pixels)
+}
+
+#[test]
+fn font_grid() {
+    let cpu = run(FONT_GRID, Quirks::VIP).unwrap();
+    assert_eq!(screen_hash(&cpu), 0xd8eb4ba1fe95c97c);
+}
+
+#[test]
+fn bcd() {
+    let cpu = run(BCD, Quirks::VIP).unwrap();
+    assert_eq!(screen_hash(&cpu), 0xe948e50522fb6296);
+}
+
+#[test]
+fn corner_clipped() {
+    let cpu = run(CORNER, Quirks::VIP).unwrap();
+    assert_eq!(screen_hash(&cpu), 0xcb9790bc8d3195c9);
+}
+
+#[test]
+fn corner_wrapped() {
+    let cpu = run(CORNER, Quirks::XO_CHIP).unwrap();
+    assert_eq!(screen_hash(&cpu), 0xe6beacecc45b536d);
+}
+
+#[test]
+fn schip() {
+    let cpu = run(SCHIP, Quirks::SCHIP).unwrap();
+    assert_eq!(screen_hash(&cpu), 0x428c2ab68ab630e3);
+}
+
+#[test]
+fn planes() {
+    let cpu = run(PLANES, Quirks::XO_CHIP).unwrap();
+    assert_eq!(screen_hash(&cpu), 0xa8de77646dd18a35);
+}
+
+#[test]
+fn recursion_within_the_stack() {
+    let cpu = run(&recursion(16), Quirks::VIP).unwrap();
+    assert_eq!((cpu.registers()[0], cpu.sp()), (16, 0));
+}
+
+#[test]
+fn recursion_overflowing_the_stack() {
+    let error = run(&recursion(17), Quirks::VIP).err();
+    assert!(matches!(error, Some(CpuError::StackOverflow { .. })), "{:?}", error);
+}
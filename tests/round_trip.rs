@@ -0,0 +1,86 @@
+//! Disassembles ROMs with the default syntax and checks that the listing reassembles to the same bytes.
+
+use chip8_interpreter::assembler::assemble;
+use chip8_interpreter::disassembler::{disassemble, Syntax};
+
+/// Uses every kind of instruction, with subroutines, sprite data and bytes that are never executed.
+const PROGRAM: &str = "
+    CLS
+    HIGH
+    LD V0, 0x20
+    LD V1, V0
+    LD I, sprite
+    CALL draw
+    SE V0, 0x20
+    JP skip
+    SNE V0, V1
+    SE V0, V1
+    SNE V0, 1
+    OR V0, V1
+    AND V0, V1
+    XOR V0, V1
+    ADD V0, V1
+    SUB V0, V1
+    SHR V0
+    SUBN V0, V1
+    SHL V0
+    ADD V2, 3
+skip:
+    RND V3, 0x0F
+    SKP V4
+    SKNP V4
+    LD V5, DT
+    LD V6, K
+    LD DT, V5
+    LD ST, V5
+    ADD I, V5
+    LD F, V5
+    LD HF, V5
+    LD B, V5
+    LD [I], V7
+    LD V7, [I]
+    LD R, V7
+    LD V7, R
+    SCD 2
+    SCR
+    SCL
+    LOW
+    PLANE 3
+    LD I, LONG pattern
+    AUDIO
+    LD PITCH, V0
+    SAVE V1, V3
+    LOAD V1, V3
+    JP V0, table
+table:
+    JP done
+done:
+    EXIT
+draw:
+    DRW V0, V1, 4
+    DRW V0, V1, 0
+    RET
+sprite:
+    SPRITE \"#..#\", \".##.\", \".##.\", \"#..#\"
+pattern:
+    DB 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF
+    DB 0x12, 0x34, 0xAB
+";
+
+#[test]
+fn default_syntax_reassembles() {
+    let rom = assemble(PROGRAM, "program.asm").expect("program should assemble");
+    let listing = disassemble(&rom, 0x200, Syntax::default());
+    let reassembled = assemble(&listing, "listing.asm").unwrap_or_else(|errors| panic!("{:?}\n{}", errors, listing));
+    assert_eq!(reassembled, rom, "{}", listing);
+}
+
+#[test]
+fn data_that_looks_like_code_reassembles() {
+    // Every two-byte value the interpreter decodes, reached only as data
+    let mut rom = vec![0x00, 0xFD]; // EXIT
+    rom.extend((0..=0xFFu8).flat_map(|byte| [byte, byte.wrapping_mul(7)]));
+    let listing = disassemble(&rom, 0x200, Syntax::default());
+    let reassembled = assemble(&listing, "listing.asm").unwrap_or_else(|errors| panic!("{:?}\n{}", errors, listing));
+    assert_eq!(reassembled, rom);
+}
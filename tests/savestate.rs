@@ -0,0 +1,56 @@
+//! Saves and loads states through save slot files.
+
+use std::fs;
+use chip8_interpreter::chip8::{Quirks, CPU};
+use chip8_interpreter::savestate::{SaveSlots, SaveStateError, FORMAT_VERSION};
+
+const ROM: [u8; 4] = [0x61, 0x05, 0xF1, 0x75]; // LD V1, 5; LD R, V1
+
+/// Runs the ROM with the given quirks and saves it to slot 0 in a fresh directory.
+fn saved(name: &str, quirks: Quirks) -> SaveSlots {
+    let directory = std::env::temp_dir().join(format!("chip8_savestate_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    let slots = SaveSlots::new(&directory, &ROM);
+    let mut cpu = CPU::new(quirks);
+    cpu.load(&ROM).unwrap();
+    for _ in 0..2 { cpu.step().unwrap(); }
+    slots.save(0, &cpu).unwrap();
+    slots
+}
+
+#[test]
+fn states_load_with_the_same_quirks() {
+    let slots = saved("same", Quirks::SCHIP);
+    let mut cpu = CPU::new(Quirks::SCHIP);
+    cpu.load(&ROM).unwrap();
+    slots.load(0, &mut cpu).unwrap();
+    assert_eq!(cpu.registers()[1], 5);
+    let _ = fs::remove_dir_all(slots.path().parent().unwrap());
+}
+
+#[test]
+fn states_with_other_quirks_are_rejected() {
+    let slots = saved("other", Quirks::SCHIP);
+    let mut cpu = CPU::new(Quirks::VIP);
+    cpu.load(&ROM).unwrap();
+    let error = slots.load(0, &mut cpu).unwrap_err();
+    assert!(matches!(error, SaveStateError::WrongQuirks { expected: Quirks::VIP, found: Quirks::SCHIP }), "{}", error);
+    // Saving with other quirks leaves the existing states alone
+    assert!(matches!(slots.save(1, &cpu), Err(SaveStateError::WrongQuirks { .. })));
+    let _ = fs::remove_dir_all(slots.path().parent().unwrap());
+}
+
+#[test]
+fn saving_over_a_newer_file_leaves_it_untouched() {
+    let slots = saved("newer", Quirks::SCHIP);
+    let mut data = fs::read(slots.path()).unwrap();
+    data[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    fs::write(slots.path(), &data).unwrap();
+
+    let mut cpu = CPU::new(Quirks::SCHIP);
+    cpu.load(&ROM).unwrap();
+    let error = slots.save(1, &cpu).unwrap_err();
+    assert!(matches!(error, SaveStateError::UnsupportedVersion { found } if found == FORMAT_VERSION + 1), "{}", error);
+    assert_eq!(fs::read(slots.path()).unwrap(), data);
+    let _ = fs::remove_dir_all(slots.path().parent().unwrap());
+}
@@ -0,0 +1,37 @@
+//! Counts the instructions run in each frame with COSMAC VIP timing.
+
+use chip8_interpreter::assembler::assemble;
+use chip8_interpreter::chip8::{Quirks, CPU, VIP_EXECUTION_CYCLES};
+use chip8_interpreter::scheduler::{Scheduler, Timing};
+
+/// Runs a program for `frames` frames with VIP timing, returning the number of instructions run in each.
+fn instructions_per_frame(source: &str, frames: usize) -> Vec<u32> {
+    let mut cpu = CPU::new(Quirks::VIP);
+    cpu.load(&assemble(source, "test.asm").expect("program should assemble")).unwrap();
+    let mut scheduler = Scheduler::new(600);
+    scheduler.set_timing(Timing::Vip);
+    (0..frames).map(|_| {
+        scheduler.begin_frame();
+        let mut count = 0;
+        while scheduler.may_run(&cpu) {
+            cpu.step().unwrap();
+            count += 1;
+        }
+        count
+    }).collect()
+}
+
+#[test]
+fn cheap_instructions_fill_the_frame() {
+    // ADD (68 + 10 cycles) and JP (68 + 23 cycles) alternate, and each frame's overrun is taken from the next
+    let counts = instructions_per_frame("loop:\n ADD V1, 1\n JP loop", 4);
+    assert_eq!(VIP_EXECUTION_CYCLES, 1836);
+    assert_eq!(counts, [22, 22, 22, 21]);
+}
+
+#[test]
+fn clearing_the_screen_takes_almost_two_frames() {
+    // CLS (68 + 3076 cycles) overruns well into the next frame, so the loop clears the screen once every two frames
+    let counts = instructions_per_frame("loop:\n CLS\n JP loop", 6);
+    assert_eq!(counts, [1, 2, 0, 2, 0, 2]);
+}